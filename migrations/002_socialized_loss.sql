@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS socialized_loss_history (
+  id uuid PRIMARY KEY,
+  source_position_id uuid,
+  position_id uuid,
+  position_owner text,
+  symbol text,
+  haircut bigint,
+  margin_before bigint,
+  margin_after bigint,
+  created_at timestamptz DEFAULT now()
+);
@@ -0,0 +1,9 @@
+ALTER TABLE liquidation_history ADD COLUMN IF NOT EXISTS insurance_contribution bigint;
+
+CREATE TABLE IF NOT EXISTS insurance_contributions (
+  id uuid PRIMARY KEY,
+  position_id uuid,
+  symbol text,
+  amount bigint,
+  created_at timestamptz DEFAULT now()
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS position_liquidation_state (
+  position_id uuid PRIMARY KEY,
+  position_owner text,
+  symbol text,
+  stage text NOT NULL,
+  steps_in_window integer DEFAULT 0,
+  window_started_at timestamptz,
+  cooldown_until timestamptz,
+  updated_at timestamptz DEFAULT now()
+);
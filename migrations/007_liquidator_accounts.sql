@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS liquidator_accounts (
+  name text PRIMARY KEY,
+  balance bigint DEFAULT 0,
+  total_rewards bigint DEFAULT 0,
+  takeovers bigint DEFAULT 0,
+  registered_at timestamptz,
+  updated_at timestamptz DEFAULT now()
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS backstop_ledger (
+  id uuid PRIMARY KEY,
+  kind text NOT NULL,
+  provider text,
+  amount bigint,
+  shares bigint,
+  position_id uuid,
+  size bigint,
+  price bigint,
+  created_at timestamptz DEFAULT now()
+);
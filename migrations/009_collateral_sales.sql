@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS collateral_sales (
+  id uuid PRIMARY KEY,
+  owner text NOT NULL,
+  asset text NOT NULL,
+  quantity bigint NOT NULL,
+  price bigint NOT NULL,
+  proceeds bigint NOT NULL,
+  created_at timestamptz DEFAULT now()
+);
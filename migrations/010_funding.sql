@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS funding_rates (
+  id uuid PRIMARY KEY,
+  symbol text NOT NULL,
+  premium double precision NOT NULL,
+  rate double precision NOT NULL,
+  mark bigint NOT NULL,
+  index_price bigint NOT NULL,
+  created_at timestamptz DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS funding_payments (
+  id uuid PRIMARY KEY,
+  position_id uuid NOT NULL,
+  position_owner text NOT NULL,
+  symbol text NOT NULL,
+  rate double precision NOT NULL,
+  amount bigint NOT NULL,
+  created_at timestamptz DEFAULT now()
+);
@@ -0,0 +1,16 @@
+ALTER TABLE liquidation_history ADD COLUMN IF NOT EXISTS liquidation_fee bigint;
+ALTER TABLE liquidation_history ADD COLUMN IF NOT EXISTS fee_tier text;
+
+CREATE TABLE IF NOT EXISTS trades (
+  id uuid PRIMARY KEY,
+  position_id uuid NOT NULL,
+  owner text NOT NULL,
+  symbol text NOT NULL,
+  is_long boolean NOT NULL,
+  size bigint NOT NULL,
+  price bigint NOT NULL,
+  liquidity text NOT NULL,
+  fee bigint NOT NULL,
+  fee_tier text NOT NULL,
+  created_at timestamptz DEFAULT now()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE liquidation_history ADD COLUMN IF NOT EXISTS realized_pnl bigint;
+ALTER TABLE liquidation_history ADD COLUMN IF NOT EXISTS unrealized_pnl bigint;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS shadow_liquidations (
+  id uuid PRIMARY KEY,
+  position_id uuid NOT NULL,
+  position_owner text NOT NULL,
+  symbol text NOT NULL,
+  is_long boolean NOT NULL,
+  margin_mode text NOT NULL,
+  position_size bigint NOT NULL,
+  size bigint NOT NULL,
+  mark bigint NOT NULL,
+  notional bigint NOT NULL,
+  margin_ratio double precision NOT NULL,
+  maintenance_margin double precision NOT NULL,
+  target_ratio double precision NOT NULL,
+  bankrupt boolean NOT NULL,
+  created_at timestamptz DEFAULT now()
+);
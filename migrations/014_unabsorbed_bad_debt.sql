@@ -0,0 +1 @@
+ALTER TABLE liquidation_history ADD COLUMN IF NOT EXISTS unabsorbed bigint DEFAULT 0;
@@ -1,36 +1,104 @@
-use axum::{extract::State, Json};
+use axum::{extract::{Path, State}, http::{HeaderMap, StatusCode}, Json};
 use serde_json::json;
 use std::sync::Arc;
 use axum::response::IntoResponse;
-use crate::engine::EngineState;
+use crate::engine::{adl, funding, orders, risk, EngineState};
+use crate::engine::auction::{AuctionBid, AuctionEvent, AuctionStatus};
+use crate::engine::liquidation_executor::TakeoverError;
+use crate::engine::backstop::{self, BackstopError};
+use crate::engine::liquidators::LiquidatorAccount;
+use crate::engine::accounts::AccountError;
+use crate::engine::controls::{Controls, EngineMode};
+use crate::engine::insurance::BookClose;
+use crate::engine::models::{ControlsEvent, EngineEvent, LiquidationRecord, MarginMode, Trade};
+use crate::engine::orders::{OrderError, OrderRequest};
+use serde::Deserialize;
+use log::warn;
+use uuid::Uuid;
 use sqlx::Row;
 
-pub async fn health() -> impl IntoResponse {
-    Json(json!({ "status": "ok" }))
+pub async fn health(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let controls = state.controls.lock().await.clone();
+    Json(json!({ "status": "ok", "controls": controls }))
 }
 
 pub async fn get_insurance(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let marks = state.oracle.snapshot().await;
     let insurance = state.insurance.lock().await.clone();
-    Json(insurance)
+    Json(json!({
+        "total_balance": insurance.total_balance(&marks),
+        "shared": insurance.shared,
+        "markets": insurance.markets,
+        "positions": insurance.positions.iter().filter(|p| p.open).collect::<Vec<_>>(),
+    }))
 }
 
+/// Every position, with its unrealized PnL and effective leverage at the
+/// current mark.
 pub async fn get_pending(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
     let positions = state.positions.lock().await.clone();
-    Json(positions)
+    let marks = state.oracle.snapshot().await;
+
+    let out: Vec<serde_json::Value> = positions
+        .iter()
+        .map(|p| {
+            let mut v = json!(p);
+            v["unrealized_pnl"] = json!(marks.get(&p.symbol).map(|m| p.unrealized_pnl(*m) as i64));
+            v["effective_leverage"] = json!(marks.get(&p.symbol).and_then(|m| risk::effective_leverage(p, *m)));
+            v
+        })
+        .collect();
+    Json(out)
+}
+
+/// Opens a position, or adds to an open one, at mark. Needs the
+/// `x-account-key` of `req.owner`.
+pub async fn post_position(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+    Json(req): Json<OrderRequest>,
+) -> Result<Json<Trade>, ApiError> {
+    authenticate_owner(&state, &headers, &req.owner).await?;
+    let trade = orders::place(&state, &req).await.map_err(|e| {
+        let status = match e {
+            OrderError::UnknownSymbol => StatusCode::NOT_FOUND,
+            OrderError::ReduceOnly => StatusCode::CONFLICT,
+            OrderError::Shadow => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        api_error(status, e)
+    })?;
+    Ok(Json(trade))
+}
+
+/// The fee schedule and what the fee account has collected.
+pub async fn get_fees(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let account = state.fees.lock().await.clone();
+    Json(json!({
+        "schedule": state.config.fees,
+        "account": account,
+    }))
+}
+
+pub async fn get_shadow(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let shadow = state.shadow.lock().await.clone();
+    Json(json!({
+        "enabled": state.config.shadow.enabled,
+        "metrics": shadow,
+    }))
 }
 
 pub async fn get_liquidations(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
-    let rows = sqlx::query(
+    let query = sqlx::query(
         r#"SELECT id, position_id, position_owner, liquidator, symbol,
            liquidated_size, liquidation_price, margin_before, margin_after,
-           liquidator_reward, bad_debt, created_at
+           liquidator_reward, insurance_contribution, slippage, bad_debt, unabsorbed,
+           realized_pnl, unrealized_pnl, liquidation_fee, fee_tier, kind, created_at
            FROM liquidation_history
            ORDER BY created_at DESC
            LIMIT 50"#,
-    )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
+    );
+    let rows = state.db.fetch_all(query).await.unwrap_or_default();
 
     // Convert rows to JSON-friendly values using dynamic row getters
     let mapped: Vec<serde_json::Value> = rows
@@ -47,7 +115,15 @@ pub async fn get_liquidations(State(state): State<Arc<EngineState>>) -> impl Int
                 "margin_before": r.get::<i64, _>("margin_before"),
                 "margin_after": r.get::<i64, _>("margin_after"),
                 "liquidator_reward": r.get::<i64, _>("liquidator_reward"),
+                "insurance_contribution": r.get::<Option<i64>, _>("insurance_contribution"),
+                "slippage": r.get::<Option<i64>, _>("slippage"),
                 "bad_debt": r.get::<i64, _>("bad_debt"),
+                "unabsorbed": r.get::<Option<i64>, _>("unabsorbed"),
+                "realized_pnl": r.get::<Option<i64>, _>("realized_pnl"),
+                "unrealized_pnl": r.get::<Option<i64>, _>("unrealized_pnl"),
+                "liquidation_fee": r.get::<Option<i64>, _>("liquidation_fee"),
+                "fee_tier": r.get::<Option<String>, _>("fee_tier"),
+                "kind": r.get::<Option<String>, _>("kind"),
                 "created_at": r.get::<chrono::DateTime<chrono::Utc>, _>("created_at"),
             })
         })
@@ -55,3 +131,544 @@ pub async fn get_liquidations(State(state): State<Arc<EngineState>>) -> impl Int
 
     Json(mapped)
 }
+
+/// ADL queue position of each of `owner`'s open positions (1 = first to be deleveraged).
+pub async fn get_adl_rank(
+    State(state): State<Arc<EngineState>>,
+    Path(owner): Path<String>,
+) -> impl IntoResponse {
+    let positions = state.positions.lock().await.clone();
+    let marks = state.oracle.snapshot().await;
+    let accounts = state.accounts.lock().await;
+
+    let mut out = Vec::new();
+    for pos in positions.iter().filter(|p| p.open && p.owner == owner) {
+        let Some(&mark) = marks.get(&pos.symbol) else { continue };
+        let queue = adl::ranking(&positions, &accounts, &pos.symbol, pos.is_long, &marks);
+        let rank = queue.iter().position(|(id, _)| *id == pos.id).map(|r| r + 1);
+
+        out.push(json!({
+            "position_id": pos.id,
+            "symbol": pos.symbol,
+            "side": if pos.is_long { "long" } else { "short" },
+            "score": adl::score(&accounts.view(pos, &positions, &marks), mark),
+            "rank": rank,
+            "queue_len": queue.len(),
+        }));
+    }
+
+    Json(out)
+}
+
+/// Auctions (live and recently finished) with their current price.
+pub async fn get_auctions(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let auctions = state.auctions.lock().await.clone();
+    let now = state.clock.now();
+
+    let mut out = Vec::new();
+    for a in auctions {
+        let mark = state.oracle.get_mark_price(&a.symbol).await;
+        out.push(json!({
+            "current_discount_bps": a.is_live().then(|| a.discount_bps(now)),
+            "current_price": mark.filter(|_| a.is_live()).map(|m| a.price(m, now)),
+            "auction": a,
+        }));
+    }
+
+    Json(out)
+}
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn api_error(status: StatusCode, msg: impl std::fmt::Display) -> ApiError {
+    (status, Json(json!({ "error": msg.to_string() })))
+}
+
+/// Name of the registered liquidator behind the `x-api-key` header.
+async fn authenticate(state: &EngineState, headers: &HeaderMap) -> Result<String, ApiError> {
+    let key = headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "missing x-api-key"))?;
+    state.liquidators.lock().await
+        .authenticate(key)
+        .map(str::to_string)
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "unknown api key"))
+}
+
+/// Checks that the `x-account-key` header holds `owner`'s key.
+async fn authenticate_owner(state: &EngineState, headers: &HeaderMap, owner: &str) -> Result<(), ApiError> {
+    let key = headers
+        .get("x-account-key")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "missing x-account-key"))?;
+    let keys = state.owner_keys.lock().await;
+    match keys.authenticate(key) {
+        Some(holder) if holder == owner => Ok(()),
+        Some(_) => Err(api_error(StatusCode::FORBIDDEN, "account key does not belong to this account")),
+        None => Err(api_error(StatusCode::UNAUTHORIZED, "unknown account key")),
+    }
+}
+
+/// Accepts an auction at its current price; the executor settles it on its next pass.
+pub async fn post_auction_bid(
+    State(state): State<Arc<EngineState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<AuctionBid>, ApiError> {
+    let liquidator = authenticate(&state, &headers).await?;
+
+    let controls = state.controls.lock().await.clone();
+    let mut auctions = state.auctions.lock().await;
+    let auction = auctions
+        .iter_mut()
+        .find(|a| a.id == id)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "no such auction"))?;
+    let now = state.clock.now();
+    if !controls.liquidates(&auction.symbol) {
+        return Err(api_error(StatusCode::SERVICE_UNAVAILABLE, "liquidations are paused"));
+    }
+    if auction.status != AuctionStatus::Open || auction.expired(now) {
+        return Err(api_error(StatusCode::CONFLICT, "auction is not open"));
+    }
+    let mark = state.oracle.get_mark_price(&auction.symbol).await
+        .ok_or_else(|| api_error(StatusCode::SERVICE_UNAVAILABLE, "no mark price"))?;
+
+    let bid = AuctionBid {
+        liquidator,
+        price: auction.price(mark, now),
+        discount_bps: auction.discount_bps(now),
+        timestamp: now,
+    };
+    auction.bids.push(bid.clone());
+    auction.status = AuctionStatus::Accepted;
+    let _ = state.event_tx.send(EngineEvent::Auction(AuctionEvent { auction: auction.clone() }));
+
+    Ok(Json(bid))
+}
+
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    pub name: String,
+}
+
+/// Registers a liquidator and hands back its API key (shown only once).
+pub async fn register_liquidator(
+    State(state): State<Arc<EngineState>>,
+    Json(req): Json<RegisterRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let name = req.name.trim();
+    if name.is_empty() || name == "executor" || name == "insurance_fund" {
+        return Err(api_error(StatusCode::BAD_REQUEST, "invalid name"));
+    }
+    let key = state.liquidators.lock().await
+        .register(name, state.clock.now())
+        .ok_or_else(|| api_error(StatusCode::CONFLICT, "name already registered"))?;
+    Ok(Json(json!({ "name": name, "api_key": key })))
+}
+
+pub async fn get_liquidator_account(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+) -> Result<Json<LiquidatorAccount>, ApiError> {
+    let name = authenticate(&state, &headers).await?;
+    let registry = state.liquidators.lock().await;
+    let acct = registry.account(&name).cloned()
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "no such liquidator"))?;
+    Ok(Json(acct))
+}
+
+/// Open positions currently below maintenance margin.
+pub async fn get_liquidatable(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<serde_json::Value>>, ApiError> {
+    authenticate(&state, &headers).await?;
+    let positions = state.positions.lock().await.clone();
+    let marks = state.oracle.snapshot().await;
+    let accounts = state.accounts.lock().await;
+
+    let mut out = Vec::new();
+    for pos in positions.iter().filter(|p| p.open) {
+        let Some(&mark) = marks.get(&pos.symbol) else { continue };
+        let Some(ratio) = risk::margin_ratio(&accounts.view(pos, &positions, &marks), mark) else { continue };
+        if ratio >= risk::maintenance_margin(pos.leverage) { continue; }
+        out.push(json!({
+            "position_id": pos.id,
+            "position_owner": pos.owner,
+            "symbol": pos.symbol,
+            "is_long": pos.is_long,
+            "size": pos.size,
+            "mark": mark,
+            "margin_ratio": ratio,
+            "stage": pos.liquidation.stage,
+        }));
+    }
+    Ok(Json(out))
+}
+
+#[derive(Deserialize)]
+pub struct TakeoverRequest {
+    pub position_id: Uuid,
+    pub size: i64,
+}
+
+/// Takes over part of a liquidatable position at mark.
+pub async fn post_takeover(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+    Json(req): Json<TakeoverRequest>,
+) -> Result<Json<LiquidationRecord>, ApiError> {
+    let liquidator = authenticate(&state, &headers).await?;
+    state.takeover(&liquidator, req.position_id, req.size)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            let status = match e {
+                TakeoverError::NotFound => StatusCode::NOT_FOUND,
+                TakeoverError::NoMarkPrice => StatusCode::SERVICE_UNAVAILABLE,
+                TakeoverError::InvalidSize { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+                TakeoverError::Paused | TakeoverError::Shadow => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::CONFLICT,
+            };
+            api_error(status, e)
+        })
+}
+
+/// Backstop pool balance sheet, share price and PnL.
+pub async fn get_backstop(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let marks = state.oracle.snapshot().await;
+    let pool = state.backstop.lock().await.clone();
+    let nav = pool.nav(&marks);
+
+    Json(json!({
+        "nav": nav,
+        "pnl": pool.pnl(&marks),
+        "share_price": (pool.total_shares > 0).then(|| nav as f64 / pool.total_shares as f64),
+        "pool": pool,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct DepositRequest {
+    pub amount: i64,
+}
+
+#[derive(Deserialize)]
+pub struct WithdrawRequest {
+    pub shares: i64,
+}
+
+/// Deposits `amount` into the backstop pool for new shares.
+pub async fn backstop_deposit(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+    Json(req): Json<DepositRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let provider = authenticate(&state, &headers).await?;
+    let marks = state.oracle.snapshot().await;
+    let shares = state.backstop.lock().await
+        .deposit(&provider, req.amount, &marks)
+        .map_err(backstop_error)?;
+
+    backstop::log_ledger(&state.db, "deposit", &provider, req.amount, shares, None, 0, 0, state.clock.now()).await;
+    Ok(Json(json!({ "provider": provider, "amount": req.amount, "shares": shares })))
+}
+
+/// Burns `shares` and pays out their NAV.
+pub async fn backstop_withdraw(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+    Json(req): Json<WithdrawRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let provider = authenticate(&state, &headers).await?;
+    let marks = state.oracle.snapshot().await;
+    let paid = state.backstop.lock().await
+        .withdraw(&provider, req.shares, &marks)
+        .map_err(backstop_error)?;
+
+    backstop::log_ledger(&state.db, "withdraw", &provider, paid, req.shares, None, 0, 0, state.clock.now()).await;
+    Ok(Json(json!({ "provider": provider, "shares": req.shares, "amount": paid })))
+}
+
+fn backstop_error(e: BackstopError) -> ApiError {
+    let status = match e {
+        BackstopError::InvalidAmount => StatusCode::BAD_REQUEST,
+        _ => StatusCode::CONFLICT,
+    };
+    api_error(status, e)
+}
+
+/// Cross-margin account: collateral, equity and maintenance requirement
+/// across the owner's cross positions.
+pub async fn get_account(
+    State(state): State<Arc<EngineState>>,
+    Path(owner): Path<String>,
+) -> impl IntoResponse {
+    let marks = state.oracle.snapshot().await;
+    let positions = state.positions.lock().await.clone();
+    let accounts = state.accounts.lock().await;
+
+    let cross: Vec<_> = positions
+        .iter()
+        .filter(|p| p.open && p.owner == owner && p.margin_mode == MarginMode::Cross)
+        .map(|p| p.id)
+        .collect();
+    Json(json!({
+        "owner": owner,
+        "collateral": accounts.collateral(&owner),
+        "assets": accounts.accounts.get(&owner).map(|a| a.assets.clone()).unwrap_or_default(),
+        "collateral_value": accounts.collateral_value(&owner, &marks),
+        "equity": accounts.equity(&owner, &positions, &marks),
+        "requirement": accounts.requirement(&owner, &positions, &marks),
+        "positions": cross,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct CollateralRequest {
+    pub amount: i64,
+    /// Non-cash asset such as "BTC"; cash if missing.
+    #[serde(default)]
+    pub asset: Option<String>,
+}
+
+/// Issues the account's key (shown only once). Once it has one, only an
+/// operator can replace it, by sending `x-admin-key`.
+pub async fn issue_account_key(
+    State(state): State<Arc<EngineState>>,
+    Path(owner): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if owner.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "invalid owner"));
+    }
+    let key = if headers.contains_key("x-admin-key") {
+        authenticate_admin(&state, &headers)?;
+        state.owner_keys.lock().await.rotate(&owner)
+    } else {
+        state.owner_keys.lock().await
+            .issue(&owner)
+            .ok_or_else(|| api_error(StatusCode::CONFLICT, "account already has a key"))?
+    };
+    Ok(Json(json!({ "owner": owner, "api_key": key })))
+}
+
+/// Posts cash or a non-cash asset; returns the balance now held of it.
+pub async fn account_deposit(
+    State(state): State<Arc<EngineState>>,
+    Path(owner): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<CollateralRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    authenticate_owner(&state, &headers, &owner).await?;
+    let mut accounts = state.accounts.lock().await;
+    let held = match &req.asset {
+        Some(asset) => accounts.deposit_asset(&owner, asset, req.amount),
+        None => accounts.deposit(&owner, req.amount),
+    }
+    .map_err(account_error)?;
+    Ok(Json(json!({ "owner": owner, "asset": req.asset, "amount": req.amount, "held": held })))
+}
+
+/// Withdraws collateral the account's cross positions don't need.
+pub async fn account_withdraw(
+    State(state): State<Arc<EngineState>>,
+    Path(owner): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<CollateralRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    authenticate_owner(&state, &headers, &owner).await?;
+    let marks = state.oracle.snapshot().await;
+    let positions = state.positions.lock().await;
+    let mut accounts = state.accounts.lock().await;
+    let held = match &req.asset {
+        Some(asset) => accounts.withdraw_asset(&owner, asset, req.amount, &positions, &marks),
+        None => accounts.withdraw(&owner, req.amount, &positions, &marks),
+    }
+    .map_err(account_error)?;
+    Ok(Json(json!({ "owner": owner, "asset": req.asset, "amount": req.amount, "held": held })))
+}
+
+fn account_error(e: AccountError) -> ApiError {
+    let status = match e {
+        AccountError::InvalidAmount | AccountError::UnknownAsset => StatusCode::BAD_REQUEST,
+        AccountError::InsufficientCollateral => StatusCode::CONFLICT,
+    };
+    api_error(status, e)
+}
+
+/// Each symbol's estimated and last funding rate, and the open positions
+/// the estimated payment would take below maintenance.
+pub async fn get_funding(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let cfg = &state.config.funding;
+    let positions = state.positions.lock().await.clone();
+    let marks = state.oracle.snapshot().await;
+    let index = state.oracle.index_snapshot().await;
+    let book = state.funding.lock().await.clone();
+    let mut accounts = state.accounts.lock().await.clone();
+
+    let mut symbols = Vec::new();
+    for (symbol, &mark) in &marks {
+        symbols.push(json!({
+            "symbol": symbol,
+            "mark": mark,
+            "index": index.get(symbol),
+            "premium": index.get(symbol).and_then(|i| funding::premium(mark, *i)),
+            "estimated_rate": book.estimate(cfg, symbol),
+            "last": book.last.get(symbol),
+        }));
+    }
+
+    // margin ratios before and after paying the estimated funding
+    let before: Vec<Option<f64>> = positions
+        .iter()
+        .map(|p| marks.get(&p.symbol).and_then(|m| risk::margin_ratio(&accounts.view(p, &positions, &marks), *m)))
+        .collect();
+    let now = state.clock.now();
+    let rates = book.estimates(cfg, &marks, &index, now);
+    let mut after = positions.clone();
+    let payments = funding::settle(&mut after, &mut accounts, &rates, now);
+
+    let mut at_risk = Vec::new();
+    for (i, pos) in after.iter().enumerate() {
+        let Some(payment) = payments.iter().find(|p| p.position_id == pos.id) else { continue };
+        let Some(&mark) = marks.get(&pos.symbol) else { continue };
+        let Some(ratio) = risk::margin_ratio(&accounts.view(pos, &after, &marks), mark) else { continue };
+        let maintenance = risk::maintenance_margin(pos.leverage);
+        if ratio < maintenance && before[i].is_some_and(|r| r >= maintenance) {
+            at_risk.push(json!({
+                "position_id": pos.id,
+                "position_owner": pos.owner,
+                "symbol": pos.symbol,
+                "payment": payment.amount,
+                "margin_ratio": before[i],
+                "margin_ratio_after": ratio,
+            }));
+        }
+    }
+
+    Json(json!({
+        "enabled": cfg.enabled,
+        "next_settlement": book.next_settlement,
+        "symbols": symbols,
+        "at_risk": at_risk,
+    }))
+}
+
+/// Notional liquidated in the throttle's window against its limits, and
+/// the liquidatable positions queued behind them.
+pub async fn get_liquidation_queue(State(state): State<Arc<EngineState>>) -> impl IntoResponse {
+    let cfg = &state.config.throttle;
+    let marks = state.oracle.snapshot().await;
+    let mut throttle = state.throttle.lock().await;
+    throttle.expire(cfg, state.clock.now());
+
+    let symbols: Vec<serde_json::Value> = marks
+        .keys()
+        .map(|symbol| json!({
+            "symbol": symbol,
+            "used": throttle.used(Some(symbol)),
+            "limit": cfg.symbols.get(symbol),
+        }))
+        .collect();
+    Json(json!({
+        "enabled": cfg.enabled,
+        "window_ms": cfg.window_ms,
+        "used": throttle.used(None),
+        "limit": cfg.max_notional,
+        "symbols": symbols,
+        "backlog": throttle.backlog,
+    }))
+}
+
+/// Checks the `x-admin-key` header against the configured admin key.
+fn authenticate_admin(state: &EngineState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let expected = state.config.admin.api_key.as_deref()
+        .ok_or_else(|| api_error(StatusCode::FORBIDDEN, "admin API is disabled"))?;
+    match headers.get("x-admin-key").and_then(|v| v.to_str().ok()) {
+        Some(key) if key == expected => Ok(()),
+        Some(_) => Err(api_error(StatusCode::UNAUTHORIZED, "wrong x-admin-key")),
+        None => Err(api_error(StatusCode::UNAUTHORIZED, "missing x-admin-key")),
+    }
+}
+
+/// Logs and broadcasts the controls after an operator change.
+fn announce_controls(state: &EngineState, controls: &Controls) {
+    warn!("Engine controls changed: mode {}, paused {:?}, reduce-only {} {:?}",
+        controls.mode.as_str(), controls.paused_symbols, controls.reduce_only, controls.reduce_only_symbols);
+    let _ = state.event_tx.send(EngineEvent::Controls(ControlsEvent {
+        controls: controls.clone(),
+        timestamp: state.clock.now(),
+    }));
+}
+
+#[derive(Deserialize)]
+pub struct ControlsRequest {
+    pub mode: Option<EngineMode>,
+    pub reduce_only: Option<bool>,
+}
+
+/// Sets the engine-wide mode and reduce-only switch.
+pub async fn post_controls(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+    Json(req): Json<ControlsRequest>,
+) -> Result<Json<Controls>, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    let mut controls = state.controls.lock().await;
+    if let Some(mode) = req.mode { controls.mode = mode; }
+    if let Some(on) = req.reduce_only { controls.reduce_only = on; }
+    announce_controls(&state, &controls);
+    Ok(Json(controls.clone()))
+}
+
+#[derive(Deserialize)]
+pub struct SymbolControlsRequest {
+    pub paused: Option<bool>,
+    pub reduce_only: Option<bool>,
+}
+
+/// Pauses liquidations or sets reduce-only on one symbol.
+pub async fn post_symbol_controls(
+    State(state): State<Arc<EngineState>>,
+    Path(symbol): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<SymbolControlsRequest>,
+) -> Result<Json<Controls>, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    let mut controls = state.controls.lock().await;
+    Controls::toggle(&mut controls.paused_symbols, &symbol, req.paused);
+    Controls::toggle(&mut controls.reduce_only_symbols, &symbol, req.reduce_only);
+    announce_controls(&state, &controls);
+    Ok(Json(controls.clone()))
+}
+
+/// Closes what the venue will take of the insurance fund's book at mark.
+pub async fn post_insurance_unwind(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<BookClose>>, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    let marks = state.oracle.snapshot().await;
+    let closes = state.insurance.lock().await.unwind(state.venue.as_ref(), &marks);
+    for c in &closes {
+        warn!("Insurance fund unwound {} of pos {} on {} at {} (PnL {})", c.size, c.position_id, c.symbol, c.price, c.pnl);
+    }
+    Ok(Json(closes))
+}
+
+/// Closes what the venue will take of the backstop pool's positions at mark.
+pub async fn post_backstop_unwind(
+    State(state): State<Arc<EngineState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<BookClose>>, ApiError> {
+    authenticate_admin(&state, &headers)?;
+    let marks = state.oracle.snapshot().await;
+    let closes = state.backstop.lock().await.unwind(state.venue.as_ref(), &marks);
+    let now = state.clock.now();
+    for c in &closes {
+        backstop::log_ledger(&state.db, "unwind", "backstop", c.pnl, 0, Some(c.position_id), c.size, c.price, now).await;
+    }
+    Ok(Json(closes))
+}
@@ -0,0 +1,54 @@
+//! Replays a price history against a position snapshot and writes what the
+//! engine would have liquidated.
+//!
+//!     backtest <snapshot.json> <prices.csv> [--step-ms 1200] [--out backtest-report]
+//!
+//! The engine config comes from `ENGINE_CONFIG`, as for the server.
+
+use std::path::PathBuf;
+use anyhow::{bail, Context};
+use goquant_liquidation_backend::engine::backtest::{self, Snapshot};
+use goquant_liquidation_backend::engine::config::EngineConfig;
+
+const USAGE: &str = "usage: backtest <snapshot.json> <prices.csv> [--step-ms N] [--out DIR]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    env_logger::init();
+
+    let mut files = Vec::new();
+    let mut step_ms = 1200;
+    let mut out = PathBuf::from("backtest-report");
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--step-ms" => step_ms = args.next().context(USAGE)?.parse().context("--step-ms")?,
+            "--out" => out = args.next().context(USAGE)?.into(),
+            _ if arg.starts_with("--") => bail!("unknown option {}\n{}", arg, USAGE),
+            _ => files.push(arg),
+        }
+    }
+    let [snapshot, prices] = files.as_slice() else { bail!(USAGE) };
+
+    let config = EngineConfig::load()?;
+    let snapshot: Snapshot = serde_json::from_str(&std::fs::read_to_string(snapshot).with_context(|| snapshot.clone())?)
+        .with_context(|| format!("parsing {}", snapshot))?;
+    let prices = backtest::parse_prices(&std::fs::read_to_string(prices).with_context(|| prices.clone())?)
+        .with_context(|| format!("parsing {}", prices))?;
+
+    let report = backtest::run(config, snapshot, &prices, step_ms).await?;
+
+    std::fs::create_dir_all(&out)?;
+    std::fs::write(out.join("report.json"), serde_json::to_string_pretty(&report)? + "\n")?;
+    std::fs::write(out.join("symbols.csv"), report.symbols_csv())?;
+    std::fs::write(out.join("insurance.csv"), report.insurance_csv())?;
+
+    println!("{} passes, {} liquidations, {} notional liquidated, {} bad debt covered, {} unabsorbed, {} socialized",
+        report.passes, report.liquidations, report.liquidated_notional, report.bad_debt, report.unabsorbed, report.socialized_loss);
+    if let (Some(first), Some(last)) = (report.insurance.first(), report.insurance.last()) {
+        println!("insurance fund: {} -> {}", first.balance, last.balance);
+    }
+    println!("report written to {}", out.display());
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::engine::config::{CollateralAssetConfig, EngineConfig, PortfolioMarginConfig};
+use crate::engine::execution::ExecutionVenue;
+use crate::engine::models::{CollateralSale, MarginMode, Position};
+use crate::engine::{portfolio, risk};
+
+/// Collateral shared by an owner's cross-margin positions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Account {
+    pub owner: String,
+    /// Cash.
+    pub collateral: i64,
+    /// Quantity held of each non-cash asset.
+    #[serde(default)]
+    pub assets: BTreeMap<String, i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountError {
+    InvalidAmount,
+    UnknownAsset,
+    InsufficientCollateral,
+}
+
+impl std::fmt::Display for AccountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountError::InvalidAmount => write!(f, "amount must be positive"),
+            AccountError::UnknownAsset => write!(f, "asset is not accepted as collateral"),
+            AccountError::InsufficientCollateral => write!(f, "withdrawal would leave the account below maintenance"),
+        }
+    }
+}
+
+/// Maintenance margin `pos` needs at `mark`.
+pub fn requirement(pos: &Position, mark: i64) -> i64 {
+    (pos.size as f64 * mark as f64 * risk::maintenance_margin(pos.leverage)) as i64
+}
+
+fn cross<'a>(positions: &'a [Position], owner: &'a str) -> impl Iterator<Item = &'a Position> {
+    positions.iter().filter(move |p| p.open && p.owner == owner && p.margin_mode == MarginMode::Cross)
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Accounts {
+    pub accounts: BTreeMap<String, Account>,
+    #[serde(skip)]
+    rules: HashMap<String, CollateralAssetConfig>,
+    /// Set when accounts are portfolio-margined.
+    #[serde(skip)]
+    portfolio: Option<PortfolioMarginConfig>,
+}
+
+impl Accounts {
+    pub fn from_config(cfg: &EngineConfig) -> Self {
+        Self {
+            accounts: BTreeMap::new(),
+            rules: cfg.collateral.assets.clone(),
+            portfolio: cfg.portfolio_margin.enabled.then(|| cfg.portfolio_margin.clone()),
+        }
+    }
+
+    pub fn collateral(&self, owner: &str) -> i64 {
+        self.accounts.get(owner).map_or(0, |a| a.collateral)
+    }
+
+    fn account(&mut self, owner: &str) -> &mut Account {
+        self.accounts
+            .entry(owner.to_string())
+            .or_insert_with(|| Account { owner: owner.to_string(), ..Default::default() })
+    }
+
+    pub fn deposit(&mut self, owner: &str, amount: i64) -> Result<i64, AccountError> {
+        if amount <= 0 { return Err(AccountError::InvalidAmount); }
+        let acct = self.account(owner);
+        acct.collateral = acct.collateral.saturating_add(amount);
+        Ok(acct.collateral)
+    }
+
+    /// Posts `quantity` of a non-cash asset; returns the quantity now held.
+    pub fn deposit_asset(&mut self, owner: &str, asset: &str, quantity: i64) -> Result<i64, AccountError> {
+        if quantity <= 0 { return Err(AccountError::InvalidAmount); }
+        if !self.rules.contains_key(asset) { return Err(AccountError::UnknownAsset); }
+        let held = self.account(owner).assets.entry(asset.to_string()).or_default();
+        *held = held.saturating_add(quantity);
+        Ok(*held)
+    }
+
+    /// Withdraws `quantity` of a non-cash asset as long as the account
+    /// stays above its maintenance requirement.
+    pub fn withdraw_asset(
+        &mut self,
+        owner: &str,
+        asset: &str,
+        quantity: i64,
+        positions: &[Position],
+        marks: &HashMap<String, i64>,
+    ) -> Result<i64, AccountError> {
+        if quantity <= 0 { return Err(AccountError::InvalidAmount); }
+        let held = self.accounts.get(owner).and_then(|a| a.assets.get(asset)).copied().unwrap_or(0);
+        if quantity > held { return Err(AccountError::InsufficientCollateral); }
+
+        let mut after = self.clone();
+        after.account(owner).assets.insert(asset.to_string(), held - quantity);
+        if after.equity(owner, positions, marks) < after.requirement(owner, positions, marks) {
+            return Err(AccountError::InsufficientCollateral);
+        }
+        *self = after;
+        Ok(held - quantity)
+    }
+
+    /// Cash plus each non-cash asset at its market value less haircut,
+    /// capped at its concentration limit.
+    pub fn collateral_value(&self, owner: &str, marks: &HashMap<String, i64>) -> i64 {
+        let Some(acct) = self.accounts.get(owner) else { return 0 };
+        let valued: Vec<(i128, u32)> = acct.assets
+            .iter()
+            .filter_map(|(asset, qty)| {
+                let rule = self.rules.get(asset)?;
+                let price = *marks.get(&rule.symbol)?;
+                let value = *qty as i128 * price as i128 * (10_000 - rule.haircut_bps.min(10_000)) as i128 / 10_000;
+                Some((value, rule.max_share_bps))
+            })
+            .collect();
+
+        let total = acct.collateral.max(0) as i128 + valued.iter().map(|v| v.0).sum::<i128>();
+        let assets: i128 = valued
+            .iter()
+            .map(|(value, max_share)| (*value).min(total * *max_share as i128 / 10_000))
+            .sum();
+        (acct.collateral as i128 + assets) as i64
+    }
+
+    /// Sells non-cash collateral for cash at the venue, most heavily
+    /// haircut asset first, until the account is back at its maintenance
+    /// requirement or has nothing left to sell. Selling frees the haircut.
+    pub fn sell_collateral(
+        &mut self,
+        owner: &str,
+        positions: &[Position],
+        marks: &HashMap<String, i64>,
+        venue: &dyn ExecutionVenue,
+        now: DateTime<Utc>,
+    ) -> Vec<CollateralSale> {
+        let Some(acct) = self.accounts.get(owner) else { return Vec::new() };
+        let mut held: Vec<(String, CollateralAssetConfig)> = acct.assets
+            .iter()
+            .filter(|(_, qty)| **qty > 0)
+            .filter_map(|(asset, _)| self.rules.get(asset).map(|r| (asset.clone(), r.clone())))
+            .collect();
+        held.sort_by(|a, b| b.1.haircut_bps.cmp(&a.1.haircut_bps).then(a.0.cmp(&b.0)));
+
+        let mut sales = Vec::new();
+        for (asset, rule) in held {
+            let shortfall = self.requirement(owner, positions, marks) - self.equity(owner, positions, marks);
+            if shortfall <= 0 { break; }
+            let Some(&price) = marks.get(&rule.symbol) else { continue };
+
+            let gain = price as i128 * rule.haircut_bps as i128 / 10_000;
+            if gain <= 0 { continue; }
+            let acct = self.account(owner);
+            let qty = ((shortfall as i128 + gain - 1) / gain).min(acct.assets[&asset] as i128) as i64;
+
+            let fill = venue.execute(&rule.symbol, true, qty, price);
+            if fill.filled <= 0 { continue; }
+            let proceeds = (fill.filled as i128 * fill.vwap as i128) as i64;
+            *acct.assets.get_mut(&asset).expect("held asset") -= fill.filled;
+            acct.collateral = acct.collateral.saturating_add(proceeds);
+
+            sales.push(CollateralSale {
+                id: Uuid::new_v4(),
+                owner: owner.to_string(),
+                asset,
+                quantity: fill.filled,
+                price: fill.vwap,
+                proceeds,
+                timestamp: now,
+            });
+        }
+        sales
+    }
+
+    /// Adds `amount` (negative to debit) to the account's cash, e.g. funding.
+    pub fn credit(&mut self, owner: &str, amount: i64) {
+        let acct = self.account(owner);
+        acct.collateral = acct.collateral.saturating_add(amount);
+    }
+
+    /// Withdraws `amount` as long as the account stays above its
+    /// maintenance requirement.
+    pub fn withdraw(
+        &mut self,
+        owner: &str,
+        amount: i64,
+        positions: &[Position],
+        marks: &HashMap<String, i64>,
+    ) -> Result<i64, AccountError> {
+        if amount <= 0 { return Err(AccountError::InvalidAmount); }
+        let free = self.equity(owner, positions, marks) - self.requirement(owner, positions, marks);
+        if amount > free.min(self.collateral(owner)) {
+            return Err(AccountError::InsufficientCollateral);
+        }
+        let acct = self.account(owner);
+        acct.collateral -= amount;
+        Ok(acct.collateral)
+    }
+
+    /// Collateral value plus margin and unrealized PnL of every cross position.
+    pub fn equity(&self, owner: &str, positions: &[Position], marks: &HashMap<String, i64>) -> i64 {
+        let upnl: i128 = cross(positions, owner)
+            .map(|p| p.margin as i128 + marks.get(&p.symbol).map_or(0, |m| p.unrealized_pnl(*m)))
+            .sum();
+        (self.collateral_value(owner, marks) as i128 + upnl) as i64
+    }
+
+    /// Maintenance requirement of the account's cross positions: the sum
+    /// of each one's, or their scenario requirement when portfolio-margined.
+    pub fn requirement(&self, owner: &str, positions: &[Position], marks: &HashMap<String, i64>) -> i64 {
+        if let Some(cfg) = &self.portfolio {
+            return portfolio::requirement(cfg, cross(positions, owner), marks);
+        }
+        cross(positions, owner)
+            .filter_map(|p| marks.get(&p.symbol).map(|m| requirement(p, *m)))
+            .sum()
+    }
+
+    /// Initial margin of the account's cross positions at their leverage.
+    pub fn initial_requirement(&self, owner: &str, positions: &[Position], marks: &HashMap<String, i64>) -> i64 {
+        cross(positions, owner)
+            .filter_map(|p| marks.get(&p.symbol).map(|m| risk::initial_margin(p.size as i128 * *m as i128, p.leverage)))
+            .sum()
+    }
+
+    /// `pos` as the risk checks should see it. A cross position gets its
+    /// own maintenance requirement scaled by the account's equity over the
+    /// account's requirement as margin, so it falls below maintenance
+    /// exactly when the account does. Isolated positions are returned as
+    /// they are.
+    pub fn view(&self, pos: &Position, positions: &[Position], marks: &HashMap<String, i64>) -> Position {
+        let mut view = pos.clone();
+        if pos.margin_mode != MarginMode::Cross || !pos.open { return view; }
+        let Some(&mark) = marks.get(&pos.symbol) else { return view };
+
+        let equity = self.equity(&pos.owner, positions, marks) as i128;
+        let total = self.requirement(&pos.owner, positions, marks) as i128;
+        let share = if total > 0 { equity * requirement(pos, mark) as i128 / total } else { equity };
+        view.margin = (share - pos.unrealized_pnl(mark)) as i64;
+        view
+    }
+
+    /// Moves collateral onto a cross position so its margin matches its
+    /// `view`, ahead of liquidating it like an isolated one.
+    pub fn lend(&mut self, positions: &mut [Position], i: usize, marks: &HashMap<String, i64>) {
+        if positions[i].margin_mode != MarginMode::Cross { return; }
+        let view = self.view(&positions[i], positions, marks);
+        let lent = view.margin - positions[i].margin;
+        self.account(&view.owner).collateral -= lent;
+        positions[i].margin = view.margin;
+    }
+
+    /// Returns what's left of a cross position's margin (`released`) to
+    /// its account once a liquidation pass is done with it.
+    pub fn settle(&mut self, pos: &mut Position, released: i64) {
+        if pos.margin_mode != MarginMode::Cross { return; }
+        let acct = self.account(&pos.owner);
+        acct.collateral = acct.collateral.saturating_add(released);
+        pos.margin = 0;
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::engine::accounts::Accounts;
+use crate::engine::models::Position;
+
+/// Price at which the position's equity is exactly zero.
+pub fn bankruptcy_price(pos: &Position) -> i64 {
+    if pos.size <= 0 { return pos.entry_price; }
+    let per_unit = pos.margin / pos.size;
+    if pos.is_long {
+        pos.entry_price.saturating_sub(per_unit).max(0)
+    } else {
+        pos.entry_price.saturating_add(per_unit)
+    }
+}
+
+/// ADL priority: PnL% on margin times leverage. Only profitable positions
+/// with margin are eligible; a cross position should be passed as its
+/// `Accounts::view`, its margin being its share of the account's equity.
+pub fn score(pos: &Position, mark: i64) -> Option<f64> {
+    let pnl = pos.unrealized_pnl(mark);
+    if pnl <= 0 || pos.margin <= 0 { return None; }
+    Some(pnl as f64 / pos.margin as f64 * pos.leverage as f64)
+}
+
+/// Open positions on one side of `symbol`, highest ADL priority first.
+pub fn ranking(
+    positions: &[Position],
+    accounts: &Accounts,
+    symbol: &str,
+    is_long: bool,
+    marks: &HashMap<String, i64>,
+) -> Vec<(Uuid, f64)> {
+    let Some(&mark) = marks.get(symbol) else { return Vec::new() };
+    let mut ranked: Vec<(Uuid, f64)> = positions
+        .iter()
+        .filter(|p| p.open && p.symbol == symbol && p.is_long == is_long && p.size > 0)
+        .filter_map(|p| score(&accounts.view(p, positions, marks), mark).map(|s| (p.id, s)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+/// One counterparty closed by ADL.
+#[derive(Clone, Debug)]
+pub struct AdlFill {
+    pub position_id: Uuid,
+    pub size: i64,
+    pub margin_before: i64,
+    pub margin_after: i64,
+    pub realized: i64,
+}
+
+/// Closes up to `size` of the side opposite to `source` at `price`, walking
+/// the ADL queue. Realized PnL on the closed part settles into margin, or
+/// into the account for a cross counterparty.
+pub fn deleverage(
+    positions: &mut [Position],
+    accounts: &mut Accounts,
+    source: &Position,
+    size: i64,
+    price: i64,
+    marks: &HashMap<String, i64>,
+) -> Vec<AdlFill> {
+    let queue = ranking(positions, accounts, &source.symbol, !source.is_long, marks);
+    let mut remaining = size;
+    let mut fills = Vec::new();
+
+    for (id, _) in queue {
+        if remaining <= 0 { break; }
+        let Some(i) = positions.iter().position(|p| p.id == id) else { continue };
+        accounts.lend(positions, i, marks);
+        let cp = &mut positions[i];
+
+        let qty = remaining.min(cp.size);
+        let margin_before = cp.margin;
+        let realized = cp.realize(qty, price);
+        if cp.size <= 0 { cp.open = false; }
+        let margin_after = cp.margin;
+        accounts.settle(cp, margin_after);
+        remaining -= qty;
+
+        fills.push(AdlFill { position_id: cp.id, size: qty, margin_before, margin_after, realized });
+    }
+
+    fills
+}
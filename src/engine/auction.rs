@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::engine::config::{AuctionConfig, DiscountCurve};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionStatus {
+    Open,
+    /// A liquidator took it; the executor settles on its next pass.
+    Accepted,
+    Settled,
+    /// Nobody took it in time; the insurance fund took the position over.
+    Expired,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuctionBid {
+    pub liquidator: String,
+    pub price: i64,
+    pub discount_bps: u32,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A slice of a liquidatable position offered at a discount to mark that
+/// grows until someone accepts it or it times out.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Auction {
+    pub id: Uuid,
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    /// Side of the position on offer; the winner takes it over.
+    pub is_long: bool,
+    pub size: i64,
+    pub start_discount_bps: u32,
+    pub max_discount_bps: u32,
+    pub curve: DiscountCurve,
+    pub started_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub status: AuctionStatus,
+    pub bids: Vec<AuctionBid>,
+}
+
+impl Auction {
+    pub fn new(cfg: &AuctionConfig, position_id: Uuid, owner: &str, symbol: &str, is_long: bool, size: i64, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            position_id,
+            position_owner: owner.to_string(),
+            symbol: symbol.to_string(),
+            is_long,
+            size,
+            start_discount_bps: cfg.start_discount_bps,
+            max_discount_bps: cfg.max_discount_bps.max(cfg.start_discount_bps),
+            curve: cfg.curve,
+            started_at: now,
+            expires_at: now + chrono::Duration::milliseconds(cfg.timeout_ms as i64),
+            status: AuctionStatus::Open,
+            bids: Vec::new(),
+        }
+    }
+
+    /// Current discount to mark, growing from start to max over the auction.
+    pub fn discount_bps(&self, now: DateTime<Utc>) -> u32 {
+        let span = (self.max_discount_bps - self.start_discount_bps) as f64;
+        let elapsed = (now - self.started_at).num_milliseconds().max(0) as f64;
+        let grown = match self.curve {
+            DiscountCurve::Linear => {
+                let total = (self.expires_at - self.started_at).num_milliseconds().max(1) as f64;
+                span * (elapsed / total).min(1.0)
+            }
+            DiscountCurve::Exponential { half_life_ms } => {
+                span * (1.0 - 0.5f64.powf(elapsed / half_life_ms.max(1) as f64))
+            }
+        };
+        self.start_discount_bps + grown as u32
+    }
+
+    /// Price a taker pays (long on offer) or receives (short on offer) now.
+    pub fn price(&self, mark: i64, now: DateTime<Utc>) -> i64 {
+        let discount = (mark as i128 * self.discount_bps(now) as i128 / 10_000) as i64;
+        if self.is_long { mark - discount } else { mark + discount }
+    }
+
+    pub fn expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+
+    pub fn is_live(&self) -> bool {
+        matches!(self.status, AuctionStatus::Open | AuctionStatus::Accepted)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuctionEvent {
+    pub auction: Auction,
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::engine::config::BackstopConfig;
+use crate::engine::execution::{self, ExecutionVenue};
+use crate::engine::insurance::BookClose;
+use crate::engine::models::Position;
+use crate::engine::store::Store;
+
+/// Pre-committed liquidity that takes over positions nobody else will
+/// absorb. Providers own the pool pro-rata to their shares; the pool's
+/// positions are backed by its cash, so their PnL moves the share price.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BackstopPool {
+    pub cash: i64,
+    pub total_shares: i64,
+    pub shares: BTreeMap<String, i64>,
+    /// Deposits minus withdrawals, to tell pool PnL apart from flows.
+    pub net_deposits: i64,
+    pub fees_earned: i64,
+    pub positions: Vec<Position>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BackstopError {
+    InvalidAmount,
+    InsufficientShares,
+    InsufficientCash,
+}
+
+impl std::fmt::Display for BackstopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackstopError::InvalidAmount => write!(f, "amount must be positive"),
+            BackstopError::InsufficientShares => write!(f, "not enough shares"),
+            BackstopError::InsufficientCash => write!(f, "pool cash is tied up in positions"),
+        }
+    }
+}
+
+impl BackstopPool {
+    /// Cash plus the unrealized PnL of every open pool position.
+    pub fn nav(&self, marks: &HashMap<String, i64>) -> i64 {
+        let upnl: i128 = self
+            .positions
+            .iter()
+            .filter(|p| p.open)
+            .filter_map(|p| marks.get(&p.symbol).map(|m| p.unrealized_pnl(*m)))
+            .sum();
+        (self.cash as i128 + upnl) as i64
+    }
+
+    pub fn pnl(&self, marks: &HashMap<String, i64>) -> i64 {
+        self.nav(marks) - self.net_deposits
+    }
+
+    fn notional(&self, marks: &HashMap<String, i64>) -> i128 {
+        self.positions
+            .iter()
+            .filter(|p| p.open)
+            .filter_map(|p| marks.get(&p.symbol).map(|m| p.size as i128 * *m as i128))
+            .sum()
+    }
+
+    /// How many units of a position on the `is_long` side the pool can
+    /// take over at `price` without going past `max_leverage` times its NAV
+    /// at `mark`, or losing more than `max_loss_bps` of its NAV on the spot.
+    pub fn capacity(&self, cfg: &BackstopConfig, marks: &HashMap<String, i64>, is_long: bool, price: i64, mark: i64) -> i64 {
+        if mark <= 0 { return 0; }
+        let nav = self.nav(marks) as i128;
+        let room = nav * cfg.max_leverage as i128 - self.notional(marks);
+        let mut units = room.max(0) / mark as i128;
+
+        let loss_per_unit = if is_long { price - mark } else { mark - price };
+        if loss_per_unit > 0 {
+            let loss_room = nav.max(0) * cfg.max_loss_bps as i128 / 10_000;
+            units = units.min(loss_room / loss_per_unit as i128);
+        }
+        units.min(i64::MAX as i128) as i64
+    }
+
+    pub fn deposit(&mut self, provider: &str, amount: i64, marks: &HashMap<String, i64>) -> Result<i64, BackstopError> {
+        if amount <= 0 { return Err(BackstopError::InvalidAmount); }
+        let nav = self.nav(marks);
+        let minted = if self.total_shares == 0 || nav <= 0 {
+            amount
+        } else {
+            (amount as i128 * self.total_shares as i128 / nav as i128) as i64
+        };
+
+        self.cash += amount;
+        self.net_deposits += amount;
+        self.total_shares += minted;
+        *self.shares.entry(provider.to_string()).or_default() += minted;
+        Ok(minted)
+    }
+
+    /// Burns `shares` and pays out their NAV from cash.
+    pub fn withdraw(&mut self, provider: &str, shares: i64, marks: &HashMap<String, i64>) -> Result<i64, BackstopError> {
+        if shares <= 0 { return Err(BackstopError::InvalidAmount); }
+        let held = self.shares.get(provider).copied().unwrap_or(0);
+        if held < shares { return Err(BackstopError::InsufficientShares); }
+
+        let amount = (self.nav(marks).max(0) as i128 * shares as i128 / self.total_shares as i128) as i64;
+        if amount > self.cash { return Err(BackstopError::InsufficientCash); }
+
+        self.cash -= amount;
+        self.net_deposits -= amount;
+        self.total_shares -= shares;
+        if held == shares {
+            self.shares.remove(provider);
+        } else {
+            self.shares.insert(provider.to_string(), held - shares);
+        }
+        Ok(amount)
+    }
+
+    /// Takes over `size` of `source` at `price`, earning `fee`.
+    pub fn take_over(&mut self, source: &Position, size: i64, price: i64, fee: i64) {
+        self.cash += fee;
+        self.fees_earned += fee;
+
+        self.positions.push(source.transferred("backstop", size, price));
+    }
+
+    /// Closes what `venue` will take of the pool's positions at `marks`,
+    /// settling their PnL into cash.
+    pub fn unwind(&mut self, venue: &dyn ExecutionVenue, marks: &HashMap<String, i64>) -> Vec<BookClose> {
+        let mut closes = Vec::new();
+        for pos in self.positions.iter_mut().filter(|p| p.open) {
+            let Some(&mark) = marks.get(&pos.symbol) else { continue };
+            let fill = execution::close_position(venue, pos, mark);
+            if fill.filled <= 0 { continue; }
+
+            let pnl = std::mem::take(&mut pos.margin);
+            self.cash = self.cash.saturating_add(pnl);
+            closes.push(BookClose { position_id: pos.id, symbol: pos.symbol.clone(), size: fill.filled, price: fill.vwap, pnl });
+        }
+        self.positions.retain(|p| p.open);
+        closes
+    }
+}
+
+/// Appends to the backstop pool's ledger.
+#[allow(clippy::too_many_arguments)]
+pub async fn log_ledger(
+    db: &Store,
+    kind: &str,
+    provider: &str,
+    amount: i64,
+    shares: i64,
+    position_id: Option<Uuid>,
+    size: i64,
+    price: i64,
+    now: DateTime<Utc>,
+) {
+    let query = sqlx::query(
+        "INSERT INTO backstop_ledger (
+                id, kind, provider, amount, shares, position_id, size, price, created_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+        )
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(provider)
+        .bind(amount)
+        .bind(shares)
+        .bind(position_id)
+        .bind(size)
+        .bind(price)
+        .bind(now);
+    let res = db.execute(query).await;
+    if let Err(e) = res {
+        error!("DB insert failed (backstop ledger): {:?}", e);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Arc;
+use anyhow::{bail, Context};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use crate::engine::clock::SimClock;
+use crate::engine::config::EngineConfig;
+use crate::engine::funding::FundingEngine;
+use crate::engine::liquidation_executor::LiquidationExecutor;
+use crate::engine::margin_call::MarginCallTracker;
+use crate::engine::models::{EngineEvent, LiquidationKind, Position};
+use crate::engine::position_monitor::PositionMonitor;
+use crate::engine::risk;
+use crate::engine::store::Store;
+use crate::engine::EngineState;
+
+/// Open positions and account balances a backtest starts from.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub positions: Vec<Position>,
+    /// Owner -> cash collateral.
+    pub deposits: HashMap<String, i64>,
+    /// Owner -> asset -> quantity.
+    pub assets: HashMap<String, HashMap<String, i64>>,
+}
+
+/// One row of a price history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceTick {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub mark: i64,
+    /// The mark itself if not given, so no funding accrues.
+    pub index: Option<i64>,
+}
+
+/// Parses `timestamp,symbol,mark[,index]` rows, oldest first. Timestamps
+/// are RFC 3339 or unix milliseconds; a header row is skipped.
+pub fn parse_prices(csv: &str) -> anyhow::Result<Vec<PriceTick>> {
+    let mut ticks = Vec::new();
+    for (n, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (n == 0 && line.starts_with("timestamp")) { continue; }
+        let cols: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [timestamp, symbol, mark, rest @ ..] = cols.as_slice() else {
+            bail!("line {}: expected timestamp,symbol,mark[,index]", n + 1);
+        };
+        let timestamp = match timestamp.parse::<i64>() {
+            Ok(ms) => DateTime::from_timestamp_millis(ms).with_context(|| format!("line {}: bad timestamp", n + 1))?,
+            Err(_) => DateTime::parse_from_rfc3339(timestamp)
+                .with_context(|| format!("line {}: bad timestamp", n + 1))?
+                .with_timezone(&Utc),
+        };
+        let index = match rest.first().filter(|s| !s.is_empty()) {
+            Some(s) => Some(s.parse().with_context(|| format!("line {}: bad index", n + 1))?),
+            None => None,
+        };
+        ticks.push(PriceTick {
+            timestamp,
+            symbol: symbol.to_string(),
+            mark: mark.parse().with_context(|| format!("line {}: bad mark", n + 1))?,
+            index,
+        });
+    }
+    ticks.sort_by_key(|t| t.timestamp);
+    Ok(ticks)
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SymbolStats {
+    pub liquidations: u64,
+    pub liquidated_size: i64,
+    pub liquidated_notional: i64,
+    /// Covered by the insurance fund.
+    pub bad_debt: i64,
+    /// Left over after the fund and socialized loss.
+    pub unabsorbed: i64,
+    pub liquidation_fees: i64,
+    pub insurance_contributions: i64,
+    /// Counterparties closed by auto-deleveraging.
+    pub adl_fills: u64,
+    pub socialized_loss: i64,
+    pub margin_calls: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InsurancePoint {
+    pub timestamp: DateTime<Utc>,
+    pub balance: i64,
+}
+
+/// What a backtest liquidated, and what it cost the insurance fund.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub passes: u64,
+    pub liquidations: u64,
+    pub liquidated_notional: i64,
+    /// Bad debt the insurance fund covered.
+    pub bad_debt: i64,
+    /// Bad debt neither the fund nor socialized loss could absorb.
+    pub unabsorbed: i64,
+    pub socialized_loss: i64,
+    pub margin_calls: u64,
+    pub kinds: BTreeMap<String, u64>,
+    /// The fund's total balance at the start, and after every pass that
+    /// changed it.
+    pub insurance: Vec<InsurancePoint>,
+    pub symbols: BTreeMap<String, SymbolStats>,
+}
+
+impl Report {
+    pub fn observe(&mut self, event: &EngineEvent) {
+        match event {
+            EngineEvent::Liquidation(e) => {
+                let r = &e.record;
+                *self.kinds.entry(r.kind.as_str().to_string()).or_default() += 1;
+                let stats = self.symbols.entry(r.symbol.clone()).or_default();
+                if r.kind == LiquidationKind::Adl {
+                    stats.adl_fills += 1;
+                    return;
+                }
+                let notional = (r.liquidated_size as i128 * r.liquidation_price as i128) as i64;
+                stats.liquidations += 1;
+                stats.liquidated_size += r.liquidated_size;
+                stats.liquidated_notional = stats.liquidated_notional.saturating_add(notional);
+                stats.bad_debt += r.bad_debt;
+                stats.unabsorbed += r.unabsorbed;
+                stats.liquidation_fees += r.liquidation_fee;
+                stats.insurance_contributions += r.insurance_contribution;
+                self.liquidations += 1;
+                self.liquidated_notional = self.liquidated_notional.saturating_add(notional);
+                self.bad_debt += r.bad_debt;
+                self.unabsorbed += r.unabsorbed;
+            }
+            EngineEvent::SocializedLoss(e) => {
+                self.symbols.entry(e.record.symbol.clone()).or_default().socialized_loss += e.record.haircut;
+                self.socialized_loss += e.record.haircut;
+            }
+            EngineEvent::MarginCall(e) => {
+                self.symbols.entry(e.symbol.clone()).or_default().margin_calls += 1;
+                self.margin_calls += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn track_insurance(&mut self, timestamp: DateTime<Utc>, balance: i64) {
+        if self.insurance.last().is_none_or(|p| p.balance != balance) {
+            self.insurance.push(InsurancePoint { timestamp, balance });
+        }
+    }
+
+    /// Per-symbol stats, one row each, then the totals.
+    pub fn symbols_csv(&self) -> String {
+        let mut out = String::from(
+            "symbol,liquidations,liquidated_size,liquidated_notional,bad_debt,unabsorbed,liquidation_fees,insurance_contributions,adl_fills,socialized_loss,margin_calls\n",
+        );
+        let mut total = SymbolStats::default();
+        for (symbol, s) in &self.symbols {
+            Self::csv_row(&mut out, symbol, s);
+            total.liquidations += s.liquidations;
+            total.liquidated_size += s.liquidated_size;
+            total.liquidated_notional = total.liquidated_notional.saturating_add(s.liquidated_notional);
+            total.bad_debt += s.bad_debt;
+            total.unabsorbed += s.unabsorbed;
+            total.liquidation_fees += s.liquidation_fees;
+            total.insurance_contributions += s.insurance_contributions;
+            total.adl_fills += s.adl_fills;
+            total.socialized_loss += s.socialized_loss;
+            total.margin_calls += s.margin_calls;
+        }
+        Self::csv_row(&mut out, "total", &total);
+        out
+    }
+
+    fn csv_row(out: &mut String, symbol: &str, s: &SymbolStats) {
+        let _ = writeln!(out, "{},{},{},{},{},{},{},{},{},{},{}",
+            symbol, s.liquidations, s.liquidated_size, s.liquidated_notional, s.bad_debt, s.unabsorbed,
+            s.liquidation_fees, s.insurance_contributions, s.adl_fills, s.socialized_loss, s.margin_calls);
+    }
+
+    pub fn insurance_csv(&self) -> String {
+        let mut out = String::from("timestamp,balance\n");
+        for p in &self.insurance {
+            let _ = writeln!(out, "{},{}", p.timestamp.to_rfc3339(), p.balance);
+        }
+        out
+    }
+}
+
+/// An engine on a simulated clock and an in-memory store, run one pass
+/// at a time: what backtests and scenario tests drive.
+pub struct Replay {
+    pub state: Arc<EngineState>,
+    clock: Arc<SimClock>,
+    monitor: PositionMonitor,
+    executor: LiquidationExecutor,
+    funding: FundingEngine,
+    margin_calls: MarginCallTracker,
+    rx: broadcast::Receiver<EngineEvent>,
+}
+
+impl Replay {
+    /// Loads `snapshot` into a fresh engine whose clock reads `start`. The
+    /// oracle starts empty; set prices before the first `step`.
+    pub async fn new(config: EngineConfig, snapshot: Snapshot, start: DateTime<Utc>) -> anyhow::Result<Self> {
+        let (tx, rx) = broadcast::channel(65_536);
+        let clock = Arc::new(SimClock::new(start));
+        let state = Arc::new(EngineState::new(Store::Memory, Arc::new(tx), config, clock.clone()).await?);
+        for pos in &snapshot.positions {
+            risk::check_leverage(pos.leverage).with_context(|| format!("position {}", pos.id))?;
+        }
+        *state.positions.lock().await = snapshot.positions;
+        {
+            let mut accounts = state.accounts.lock().await;
+            for (owner, amount) in &snapshot.deposits {
+                accounts.deposit(owner, *amount).map_err(|e| anyhow::anyhow!("deposit for {}: {}", owner, e))?;
+            }
+            for (owner, assets) in &snapshot.assets {
+                for (asset, quantity) in assets {
+                    accounts.deposit_asset(owner, asset, *quantity).map_err(|e| anyhow::anyhow!("{} deposit for {}: {}", asset, owner, e))?;
+                }
+            }
+        }
+        state.oracle.clear().await;
+
+        let funding = FundingEngine::new(state.clone());
+        if state.config.funding.enabled {
+            funding.start().await;
+        }
+        Ok(Self {
+            monitor: PositionMonitor::new(state.clone()),
+            executor: LiquidationExecutor::new(state.clone()),
+            funding,
+            margin_calls: MarginCallTracker::default(),
+            state,
+            clock,
+            rx,
+        })
+    }
+
+    /// The insurance funds' total balance, their book marked at the
+    /// oracle's prices.
+    pub async fn insurance_balance(&self) -> i64 {
+        let marks = self.state.oracle.snapshot().await;
+        self.state.insurance.lock().await.total_balance(&marks)
+    }
+
+    /// Moves the clock to `now` and runs the monitor, the executor and
+    /// funding once each. Returns the events they sent.
+    pub async fn step(&mut self, now: DateTime<Utc>) -> anyhow::Result<Vec<EngineEvent>> {
+        self.clock.set(now);
+        self.monitor.check(&mut self.margin_calls).await;
+        self.executor.pass().await;
+        if self.state.config.funding.enabled {
+            self.funding.tick().await;
+        }
+
+        let mut events = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Lagged(n)) => bail!("replay dropped {} events", n),
+                Err(_) => break,
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Replays `prices` against `snapshot` with the engine's own monitor,
+/// executor and funding logic, on a simulated clock and without a
+/// database. Time runs from the first tick to the last in steps of
+/// `step_ms`, each step taking in every price up to it and then running
+/// one pass.
+pub async fn run(config: EngineConfig, snapshot: Snapshot, prices: &[PriceTick], step_ms: u64) -> anyhow::Result<Report> {
+    let mut report = Report::default();
+    let (Some(first), Some(last)) = (prices.first(), prices.last()) else { return Ok(report) };
+    if step_ms == 0 { bail!("step must be at least 1ms"); }
+    let (start, end) = (first.timestamp, last.timestamp);
+
+    let mut replay = Replay::new(config, snapshot, start).await?;
+    report.start = Some(start);
+    report.end = Some(end);
+    report.track_insurance(start, replay.insurance_balance().await);
+
+    let mut now = start;
+    let mut next = 0;
+    loop {
+        while let Some(tick) = prices.get(next).filter(|t| t.timestamp <= now) {
+            replay.state.oracle.set(&tick.symbol, tick.mark, tick.index).await;
+            next += 1;
+        }
+        for event in replay.step(now).await? {
+            report.observe(&event);
+        }
+        report.passes += 1;
+        report.track_insurance(now, replay.insurance_balance().await);
+
+        if now >= end { break; }
+        now = (now + Duration::milliseconds(step_ms as i64)).min(end);
+    }
+    Ok(report)
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use tokio::sync::watch;
+
+/// Where the engine's tasks get the time from and wait on it.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()>;
+}
+
+/// Wall-clock time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Time that only moves when told to. Sleepers wake once the clock has
+/// been advanced past their deadline, so a run replays identically.
+#[derive(Debug)]
+pub struct SimClock {
+    now: watch::Sender<DateTime<Utc>>,
+}
+
+impl SimClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { now: watch::Sender::new(start) }
+    }
+
+    /// Moves the clock to `t`; it never goes backwards.
+    pub fn set(&self, t: DateTime<Utc>) {
+        self.now.send_if_modified(|now| {
+            if t <= *now { return false; }
+            *now = t;
+            true
+        });
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.set(after(self.now(), duration));
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        let deadline = after(self.now(), duration);
+        let mut rx = self.now.subscribe();
+        Box::pin(async move {
+            // the sender lives as long as `self`, so this can't fail
+            let _ = rx.wait_for(|now| *now >= deadline).await;
+        })
+    }
+}
+
+/// `t` plus `duration`, saturating at the end of time.
+fn after(t: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|d| t.checked_add_signed(d))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use anyhow::Context;
+use log::{info, warn};
+use crate::engine::risk;
+
+/// Engine-wide tunables. Loaded from the JSON file named by `ENGINE_CONFIG`,
+/// falling back to defaults for anything missing.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    pub socialized_loss: SocializedLossConfig,
+    pub adl: AdlConfig,
+    pub penalty: PenaltyConfig,
+    pub insurance: InsuranceConfig,
+    /// Per-symbol overrides of `market_defaults`. In a config file each
+    /// one only needs the fields it changes; `from_json` fills in the rest
+    /// from `market_defaults`.
+    pub markets: HashMap<String, MarketConfig>,
+    pub market_defaults: MarketConfig,
+    pub execution: ExecutionConfig,
+    pub auction: AuctionConfig,
+    pub liquidators: LiquidatorConfig,
+    pub backstop: BackstopConfig,
+    pub waterfall: WaterfallConfig,
+    pub margin_call: MarginCallConfig,
+    pub collateral: CollateralConfig,
+    pub portfolio_margin: PortfolioMarginConfig,
+    pub funding: FundingConfig,
+    pub fees: FeeConfig,
+    pub throttle: ThrottleConfig,
+    pub admin: AdminConfig,
+    pub shadow: ShadowConfig,
+}
+
+impl EngineConfig {
+    pub fn market(&self, symbol: &str) -> &MarketConfig {
+        self.markets.get(symbol).unwrap_or(&self.market_defaults)
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var("ENGINE_CONFIG") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path)?;
+                let cfg = Self::from_json(&raw)?;
+                info!("Loaded engine config from {}", path);
+                Ok(cfg)
+            }
+            Err(_) => {
+                warn!("ENGINE_CONFIG not set, using default engine config");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Parses a config file, layering each `markets` entry over
+    /// `market_defaults` rather than over the built-in defaults, and checks
+    /// the margin call webhooks can be delivered to.
+    pub fn from_json(raw: &str) -> anyhow::Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(raw)?;
+        let defaults = value.get("market_defaults").cloned().unwrap_or_else(|| serde_json::json!({}));
+        let defaults = serde_json::to_value(serde_json::from_value::<MarketConfig>(defaults)?)?;
+        if let Some(markets) = value.get_mut("markets").and_then(|m| m.as_object_mut()) {
+            for market in markets.values_mut() {
+                let mut layered = defaults.clone();
+                if let (Some(base), Some(fields)) = (layered.as_object_mut(), market.as_object()) {
+                    base.extend(fields.clone());
+                }
+                *market = layered;
+            }
+        }
+        let cfg: Self = serde_json::from_value(value)?;
+        cfg.margin_call.check_webhooks()?;
+        risk::check_leverage(cfg.market_defaults.max_leverage).context("market_defaults.max_leverage")?;
+        for (symbol, market) in &cfg.markets {
+            risk::check_leverage(market.max_leverage).with_context(|| format!("markets.{}.max_leverage", symbol))?;
+        }
+        Ok(cfg)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SocializedLossMode {
+    /// Haircut pro-rata to each winner's unrealized profit.
+    PnlWeighted,
+    /// Haircut pro-rata to each winner's notional (size * mark).
+    OpenInterestWeighted,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocializedLossConfig {
+    pub enabled: bool,
+    pub mode: SocializedLossMode,
+}
+
+impl Default for SocializedLossConfig {
+    fn default() -> Self {
+        Self { enabled: true, mode: SocializedLossMode::PnlWeighted }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdlConfig {
+    /// Auto-deleverage counterparties when the insurance fund can't cover a deficit.
+    pub enabled: bool,
+}
+
+impl Default for AdlConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Liquidation penalty charged on liquidated notional, in basis points.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PenaltyConfig {
+    pub rate_bps: u32,
+    /// Share of the penalty that goes to the insurance fund; the rest is the liquidator's reward.
+    pub insurance_share_bps: u32,
+}
+
+impl Default for PenaltyConfig {
+    fn default() -> Self {
+        Self { rate_bps: 250, insurance_share_bps: 5_000 }
+    }
+}
+
+impl PenaltyConfig {
+    /// Returns (liquidator reward, insurance contribution) for a liquidation
+    /// of `liquidated_value`, never charging more than the `available` equity.
+    pub fn split(&self, liquidated_value: i128, available: i64) -> (i64, i64) {
+        let penalty = (liquidated_value * self.rate_bps as i128 / 10_000)
+            .min(available.max(0) as i128) as i64;
+        let contribution = (penalty as i128 * self.insurance_share_bps.min(10_000) as i128 / 10_000) as i64;
+        (penalty - contribution, contribution)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InsuranceConfig {
+    pub shared_balance: i64,
+    /// Markets listed here get an isolated sub-fund; the rest use the shared fund.
+    pub markets: HashMap<String, MarketInsuranceConfig>,
+}
+
+impl Default for InsuranceConfig {
+    fn default() -> Self {
+        Self { shared_balance: 1_000_000, markets: HashMap::new() }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketInsuranceConfig {
+    pub initial_balance: i64,
+    /// Draw on the shared fund once the sub-fund is empty.
+    pub fallback_to_shared: bool,
+    /// Cap on everything the market may draw from the shared fund, across
+    /// all its deficits.
+    pub max_shared_draw: Option<i64>,
+    /// Contributions that lift the sub-fund above this are swept to the shared fund.
+    pub sweep_above: Option<i64>,
+}
+
+impl Default for MarketInsuranceConfig {
+    fn default() -> Self {
+        Self { initial_balance: 0, fallback_to_shared: true, max_shared_draw: None, sweep_above: None }
+    }
+}
+
+/// Margin level a partial liquidation tries to restore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidationTarget {
+    /// 1 / leverage.
+    InitialMargin,
+    /// Maintenance margin plus `target_buffer_bps`.
+    MaintenancePlusBuffer,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketConfig {
+    pub liquidation_target: LiquidationTarget,
+    pub target_buffer_bps: u32,
+    /// Most of the position a single partial step may close, in bps of size.
+    pub max_step_bps: u32,
+    /// Smallest size a single step closes, so dust positions don't linger.
+    pub min_step: i64,
+    /// Pause after a partial step before the position can be cut again.
+    pub cooldown_ms: u64,
+    /// Partial steps allowed per `step_window_ms`.
+    pub max_steps_per_window: u32,
+    pub step_window_ms: u64,
+    /// Simulated order book depth: levels per side, size per level and
+    /// spacing between levels in bps of mark.
+    pub book_levels: u32,
+    pub book_level_size: i64,
+    pub book_tick_bps: u32,
+    /// Most leverage a position may be opened at, up to `risk::MAX_LEVERAGE`;
+    /// its initial margin is notional / leverage.
+    pub max_leverage: u16,
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self {
+            liquidation_target: LiquidationTarget::MaintenancePlusBuffer,
+            target_buffer_bps: 50,
+            max_step_bps: 5_000,
+            min_step: 1,
+            cooldown_ms: 5_000,
+            max_steps_per_window: 3,
+            step_window_ms: 60_000,
+            book_levels: 10,
+            book_level_size: 100,
+            book_tick_bps: 5,
+            max_leverage: 100,
+        }
+    }
+}
+
+impl MarketConfig {
+    pub fn target_ratio(&self, leverage: u16, maintenance: f64) -> f64 {
+        match self.liquidation_target {
+            LiquidationTarget::InitialMargin => 1.0 / leverage.max(1) as f64,
+            LiquidationTarget::MaintenancePlusBuffer => maintenance + self.target_buffer_bps as f64 / 10_000.0,
+        }
+    }
+
+    /// Caps a wanted reduction of a position of `size` to this market's step limits.
+    pub fn cap_step(&self, wanted: i64, size: i64) -> i64 {
+        let cap = (size as i128 * self.max_step_bps as i128 / 10_000) as i64;
+        wanted.min(cap.max(self.min_step)).max(self.min_step.max(1)).min(size)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VenueKind {
+    /// Everything fills at mark.
+    #[default]
+    Mark,
+    /// Walk an in-process order book built from each market's book settings.
+    OrderBook,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutionConfig {
+    pub venue: VenueKind,
+}
+
+/// How an auction's discount grows from start to max.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountCurve {
+    /// Straight line, reaching max at the timeout.
+    Linear,
+    /// Halves the distance to max every `half_life_ms`.
+    Exponential { half_life_ms: u64 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuctionConfig {
+    /// Offer liquidatable positions in a Dutch auction instead of closing them directly.
+    pub enabled: bool,
+    pub start_discount_bps: u32,
+    pub max_discount_bps: u32,
+    pub curve: DiscountCurve,
+    /// After this the insurance fund takes the position over.
+    pub timeout_ms: u64,
+}
+
+impl Default for AuctionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start_discount_bps: 50,
+            max_discount_bps: 500,
+            curve: DiscountCurve::Linear,
+            timeout_ms: 30_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiquidatorConfig {
+    /// How long a newly liquidatable position is left to registered
+    /// liquidators before the executor steps in itself.
+    pub grace_ms: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackstopConfig {
+    /// Let the backstop pool take over what the book / auction can't absorb.
+    pub enabled: bool,
+    /// Fee on taken-over notional, paid to the pool by the insurance fund.
+    pub fee_bps: u32,
+    /// Pool notional may not exceed this multiple of its NAV.
+    pub max_leverage: u32,
+    /// Most of its NAV, in bps, the pool may take on as a loss at mark in
+    /// one takeover (it takes over at the bankruptcy price).
+    pub max_loss_bps: u32,
+}
+
+impl Default for BackstopConfig {
+    fn default() -> Self {
+        Self { enabled: false, fee_bps: 50, max_leverage: 5, max_loss_bps: 1_000 }
+    }
+}
+
+/// A step of the liquidation waterfall; see `engine::waterfall`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageKind {
+    /// Offer a liquidation step in a Dutch auction (needs `auction.enabled`).
+    Auction,
+    /// Send what's left to the execution venue.
+    MarketClose,
+    /// Hand a bankrupt remainder to the backstop pool (needs `backstop.enabled`).
+    Backstop,
+    /// Deleverage counterparties when the fund can't cover the deficit (needs `adl.enabled`).
+    Adl,
+    InsuranceFund,
+    /// Haircut winners (needs `socialized_loss.enabled`).
+    SocializedLoss,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaterfallConfig {
+    /// Stages a liquidation goes through, in order. Size nobody took is
+    /// closed at mark after the last stage.
+    pub stages: Vec<StageKind>,
+}
+
+impl Default for WaterfallConfig {
+    fn default() -> Self {
+        Self {
+            stages: vec![
+                StageKind::Auction,
+                StageKind::MarketClose,
+                StageKind::Backstop,
+                StageKind::Adl,
+                StageKind::InsuranceFund,
+                StageKind::SocializedLoss,
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarginCallConfig {
+    /// Warn when the margin ratio falls below these multiples of maintenance.
+    pub thresholds: Vec<f64>,
+    /// A warning re-arms once the ratio is back this far above its
+    /// threshold, in bps of the threshold.
+    pub hysteresis_bps: u32,
+    /// Plain-HTTP URLs each margin call is POSTed to as JSON.
+    pub webhooks: Vec<String>,
+}
+
+impl Default for MarginCallConfig {
+    fn default() -> Self {
+        Self { thresholds: vec![1.5, 1.2], hysteresis_bps: 1_000, webhooks: Vec::new() }
+    }
+}
+
+impl MarginCallConfig {
+    /// Rejects webhooks the plain-HTTP client can't deliver to, such as
+    /// `https://` URLs.
+    pub fn check_webhooks(&self) -> anyhow::Result<()> {
+        for url in &self.webhooks {
+            let uri: hyper::Uri = url.parse().map_err(|e| anyhow::anyhow!("margin call webhook {}: {}", url, e))?;
+            if uri.scheme_str() != Some("http") || uri.host().is_none() {
+                anyhow::bail!("margin call webhook {}: only http:// URLs are supported", url);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollateralConfig {
+    /// Non-cash assets accounts may post, by name. Cash counts in full.
+    pub assets: HashMap<String, CollateralAssetConfig>,
+}
+
+impl Default for CollateralConfig {
+    fn default() -> Self {
+        Self {
+            assets: HashMap::from([
+                ("BTC".to_string(), CollateralAssetConfig { symbol: "BTC-USD".into(), haircut_bps: 1_000, max_share_bps: 6_000 }),
+                ("ETH".to_string(), CollateralAssetConfig { symbol: "ETH-USD".into(), haircut_bps: 1_500, max_share_bps: 4_000 }),
+            ]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollateralAssetConfig {
+    /// Oracle symbol the asset is priced by.
+    pub symbol: String,
+    /// Taken off the asset's market value before it counts as collateral.
+    pub haircut_bps: u32,
+    /// Most of an account's collateral value this asset may make up.
+    pub max_share_bps: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortfolioMarginConfig {
+    /// Charge cross accounts the worst loss over a grid of price shocks
+    /// instead of the sum of each position's maintenance requirement.
+    pub enabled: bool,
+    /// Price moves tried for each group, in bps of mark.
+    pub shocks_bps: Vec<i64>,
+    /// Symbols shocked together in every scenario (e.g. a perp and its
+    /// dated future). Symbols in no group are shocked on their own.
+    pub groups: Vec<Vec<String>>,
+    /// Least an account is charged, in bps of its gross notional, so a
+    /// perfect hedge still needs some margin.
+    pub floor_bps: u32,
+}
+
+impl Default for PortfolioMarginConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shocks_bps: vec![-500, -250, 0, 250, 500],
+            groups: vec![vec!["BTC-USD".into(), "ETH-USD".into()]],
+            floor_bps: 50,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FundingConfig {
+    pub enabled: bool,
+    /// Time between funding settlements.
+    pub interval_secs: u64,
+    /// How often the mark/index premium is sampled between settlements.
+    pub sample_ms: u64,
+    /// Cap on the rate paid per interval, either way, in bps of notional.
+    pub max_rate_bps: u32,
+}
+
+impl Default for FundingConfig {
+    fn default() -> Self {
+        Self { enabled: true, interval_secs: 3_600, sample_ms: 1_000, max_rate_bps: 75 }
+    }
+}
+
+/// Where fees are paid to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeDestination {
+    FeeAccount,
+    InsuranceFund,
+}
+
+/// Fee rates on traded or liquidated notional, in bps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub maker_bps: u32,
+    pub taker_bps: u32,
+    /// Charged to the liquidated position, on top of the penalty.
+    pub liquidation_bps: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeConfig {
+    /// Tier of every account not listed in `accounts`.
+    pub default_tier: FeeTier,
+    pub tiers: HashMap<String, FeeTier>,
+    /// Owner -> name of its tier in `tiers`.
+    pub accounts: HashMap<String, String>,
+    pub destination: FeeDestination,
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            default_tier: FeeTier { maker_bps: 2, taker_bps: 5, liquidation_bps: 25 },
+            tiers: HashMap::from([
+                ("vip".to_string(), FeeTier { maker_bps: 0, taker_bps: 3, liquidation_bps: 25 }),
+            ]),
+            accounts: HashMap::new(),
+            destination: FeeDestination::FeeAccount,
+        }
+    }
+}
+
+impl FeeConfig {
+    /// `owner`'s tier name and rates.
+    pub fn tier(&self, owner: &str) -> (&str, &FeeTier) {
+        self.accounts
+            .get(owner)
+            .and_then(|name| self.tiers.get_key_value(name))
+            .map_or(("default", &self.default_tier), |(name, tier)| (name.as_str(), tier))
+    }
+}
+
+/// Caps on the notional the executor liquidates per window, so a crash
+/// doesn't dump every position into thin books at once.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    pub enabled: bool,
+    pub window_ms: u64,
+    /// Across all symbols; unlimited if missing.
+    pub max_notional: Option<i64>,
+    /// Per symbol; symbols not listed are only held to `max_notional`.
+    pub symbols: HashMap<String, i64>,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self { enabled: false, window_ms: 60_000, max_notional: None, symbols: HashMap::new() }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Key operators send as `x-admin-key`; the admin API is off without one.
+    pub api_key: Option<String>,
+}
+
+/// Shadow mode: the executor records what it would liquidate under this
+/// config to `shadow_liquidations` and changes nothing, so a rule change
+/// can be compared against production before it goes live. Funding rates
+/// are still recorded but not paid, and orders and takeovers are refused.
+/// Collateral and backstop deposits and withdrawals still go through.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowConfig {
+    pub enabled: bool,
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeSet;
+use serde::{Serialize, Deserialize};
+
+/// What the engine is allowed to do, as set by operators.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineMode {
+    #[default]
+    Running,
+    /// Liquidations off, monitoring on: positions are still checked,
+    /// margin calls and liquidatable events still go out, and the executor
+    /// logs what it would cut.
+    MonitorOnly,
+    /// Executor and monitor both stopped.
+    Paused,
+}
+
+impl EngineMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngineMode::Running => "running",
+            EngineMode::MonitorOnly => "monitor_only",
+            EngineMode::Paused => "paused",
+        }
+    }
+}
+
+/// Operator switches the executor, monitor and order entry check.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Controls {
+    pub mode: EngineMode,
+    /// Symbols the executor leaves alone while the rest run.
+    pub paused_symbols: BTreeSet<String>,
+    /// Reject orders everywhere; they can only add risk.
+    pub reduce_only: bool,
+    pub reduce_only_symbols: BTreeSet<String>,
+}
+
+impl Controls {
+    /// Whether positions on `symbol` may be liquidated right now.
+    pub fn liquidates(&self, symbol: &str) -> bool {
+        self.mode == EngineMode::Running && !self.paused_symbols.contains(symbol)
+    }
+
+    pub fn monitors(&self) -> bool {
+        self.mode != EngineMode::Paused
+    }
+
+    pub fn accepts_orders(&self, symbol: &str) -> bool {
+        !self.reduce_only && !self.reduce_only_symbols.contains(symbol)
+    }
+
+    /// Adds or removes `symbol` from `set` when `on` is given.
+    pub fn toggle(set: &mut BTreeSet<String>, symbol: &str, on: Option<bool>) {
+        match on {
+            Some(true) => { set.insert(symbol.to_string()); }
+            Some(false) => { set.remove(symbol); }
+            None => {}
+        }
+    }
+}
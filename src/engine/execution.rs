@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+use crate::engine::config::{EngineConfig, VenueKind};
+use crate::engine::models::Position;
+
+/// Result of sending a liquidation order to a venue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fill {
+    pub filled: i64,
+    /// Volume-weighted average fill price; equals mark when nothing filled.
+    pub vwap: i64,
+}
+
+impl Fill {
+    /// What filling at `vwap` instead of `mark` cost the side being closed.
+    pub fn slippage(&self, closing_long: bool, mark: i64) -> i64 {
+        let per_unit = if closing_long { mark - self.vwap } else { self.vwap - mark };
+        ((self.filled as i128) * (per_unit as i128)).max(0) as i64
+    }
+}
+
+/// Where the executor sends the size it closes.
+pub trait ExecutionVenue: Send + Sync {
+    /// Closes up to `size` of a position in `symbol`. `closing_long` means
+    /// selling into bids, otherwise buying from asks.
+    fn execute(&self, symbol: &str, closing_long: bool, size: i64, mark: i64) -> Fill;
+}
+
+/// Closes what `venue` will take of `pos` at `mark`, realizing the PnL
+/// into its margin.
+pub fn close_position(venue: &dyn ExecutionVenue, pos: &mut Position, mark: i64) -> Fill {
+    let fill = venue.execute(&pos.symbol, pos.is_long, pos.size, mark);
+    if fill.filled > 0 {
+        pos.realize(fill.filled, fill.vwap);
+        if pos.size <= 0 {
+            pos.open = false;
+        }
+    }
+    fill
+}
+
+pub fn venue_from_config(cfg: &EngineConfig) -> Box<dyn ExecutionVenue> {
+    match cfg.execution.venue {
+        VenueKind::Mark => Box::new(MarkPriceVenue),
+        VenueKind::OrderBook => Box::new(SimulatedOrderBook::new(cfg.clone())),
+    }
+}
+
+/// Fills everything at the mark price.
+pub struct MarkPriceVenue;
+
+impl ExecutionVenue for MarkPriceVenue {
+    fn execute(&self, _symbol: &str, _closing_long: bool, size: i64, mark: i64) -> Fill {
+        Fill { filled: size.max(0), vwap: mark }
+    }
+}
+
+/// Price levels around one mark. Liquidity taken stays taken until the
+/// mark moves and the book is rebuilt.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderBook {
+    pub mark: i64,
+    /// (price, size), best first.
+    pub bids: Vec<(i64, i64)>,
+    pub asks: Vec<(i64, i64)>,
+}
+
+impl OrderBook {
+    pub fn around(mark: i64, levels: u32, level_size: i64, tick_bps: u32) -> Self {
+        let step = |i: u32| (mark as i128 * (tick_bps as i128) * i as i128 / 10_000) as i64;
+        Self {
+            mark,
+            bids: (1..=levels).map(|i| ((mark - step(i)).max(0), level_size)).collect(),
+            asks: (1..=levels).map(|i| (mark + step(i), level_size)).collect(),
+        }
+    }
+
+    /// Walks the book on one side, taking up to `size`.
+    pub fn sweep(&mut self, sell: bool, size: i64) -> Fill {
+        let side = if sell { &mut self.bids } else { &mut self.asks };
+        let mut remaining = size;
+        let mut notional = 0i128;
+
+        for level in side.iter_mut() {
+            if remaining <= 0 { break; }
+            let take = remaining.min(level.1);
+            level.1 -= take;
+            remaining -= take;
+            notional += take as i128 * level.0 as i128;
+        }
+        side.retain(|l| l.1 > 0);
+
+        let filled = size - remaining;
+        let vwap = if filled > 0 { (notional / filled as i128) as i64 } else { self.mark };
+        Fill { filled, vwap }
+    }
+}
+
+/// In-process order book per symbol, shaped by each market's book settings.
+pub struct SimulatedOrderBook {
+    config: EngineConfig,
+    books: Mutex<HashMap<String, OrderBook>>,
+}
+
+impl SimulatedOrderBook {
+    pub fn new(config: EngineConfig) -> Self {
+        Self { config, books: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl ExecutionVenue for SimulatedOrderBook {
+    fn execute(&self, symbol: &str, closing_long: bool, size: i64, mark: i64) -> Fill {
+        let market = self.config.market(symbol);
+        let mut books = self.books.lock().unwrap();
+        let book = books
+            .entry(symbol.to_string())
+            .or_insert_with(|| OrderBook::around(mark, market.book_levels, market.book_level_size, market.book_tick_bps));
+        if book.mark != mark {
+            *book = OrderBook::around(mark, market.book_levels, market.book_level_size, market.book_tick_bps);
+        }
+        book.sweep(closing_long, size)
+    }
+}
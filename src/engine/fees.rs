@@ -0,0 +1,38 @@
+use serde::{Serialize, Deserialize};
+use crate::engine::config::{FeeConfig, FeeDestination};
+use crate::engine::insurance::InsuranceFunds;
+
+/// `bps` of `notional`.
+pub fn fee(notional: i128, bps: u32) -> i64 {
+    (notional * bps as i128 / 10_000) as i64
+}
+
+/// Fees collected by the exchange when they aren't routed to the
+/// insurance fund.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FeeAccount {
+    pub balance: i64,
+    pub trading: i64,
+    pub liquidation: i64,
+}
+
+impl FeeAccount {
+    pub fn collect_trading(&mut self, amount: i64) {
+        self.balance = self.balance.saturating_add(amount);
+        self.trading = self.trading.saturating_add(amount);
+    }
+
+    pub fn collect_liquidation(&mut self, amount: i64) {
+        self.balance = self.balance.saturating_add(amount);
+        self.liquidation = self.liquidation.saturating_add(amount);
+    }
+}
+
+/// Pays a trading fee on `symbol` to wherever `cfg` routes fees.
+pub fn route_trading(cfg: &FeeConfig, account: &mut FeeAccount, insurance: &mut InsuranceFunds, symbol: &str, amount: i64) {
+    if amount <= 0 { return; }
+    match cfg.destination {
+        FeeDestination::FeeAccount => account.collect_trading(amount),
+        FeeDestination::InsuranceFund => insurance.credit(symbol, amount),
+    }
+}
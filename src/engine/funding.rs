@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::time::Duration;
+use uuid::Uuid;
+use crate::engine::accounts::Accounts;
+use crate::engine::config::FundingConfig;
+use crate::engine::models::{EngineEvent, FundingEvent, FundingPayment, FundingRate, MarginMode, Position};
+use crate::engine::{risk, EngineState};
+
+/// (mark - index) / index.
+pub fn premium(mark: i64, index: i64) -> Option<f64> {
+    if index <= 0 { return None; }
+    Some((mark - index) as f64 / index as f64)
+}
+
+/// What `pos` receives (negative: pays) at `rate` on its notional at `mark`.
+pub fn payment(pos: &Position, mark: i64, rate: f64) -> i64 {
+    let owed = (pos.size as f64 * mark as f64 * rate) as i64;
+    if pos.is_long { -owed } else { owed }
+}
+
+/// Premium samples taken since the last settlement, and what was settled.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FundingBook {
+    #[serde(skip)]
+    samples: HashMap<String, (f64, u32)>,
+    pub last: HashMap<String, FundingRate>,
+    pub next_settlement: Option<DateTime<Utc>>,
+}
+
+impl FundingBook {
+    pub fn sample(&mut self, marks: &HashMap<String, i64>, index: &HashMap<String, i64>) {
+        for (symbol, &mark) in marks {
+            let Some(p) = index.get(symbol).and_then(|i| premium(mark, *i)) else { continue };
+            let (sum, n) = self.samples.entry(symbol.clone()).or_default();
+            *sum += p;
+            *n += 1;
+        }
+    }
+
+    /// Average premium since the last settlement, capped at `max_rate_bps`:
+    /// the rate `symbol` would pay if the interval closed now.
+    pub fn estimate(&self, cfg: &FundingConfig, symbol: &str) -> Option<f64> {
+        let (sum, n) = self.samples.get(symbol).filter(|s| s.1 > 0)?;
+        let cap = cfg.max_rate_bps as f64 / 10_000.0;
+        Some((sum / *n as f64).clamp(-cap, cap))
+    }
+
+    /// The rates each sampled symbol would pay if the interval closed now.
+    pub fn estimates(
+        &self,
+        cfg: &FundingConfig,
+        marks: &HashMap<String, i64>,
+        index: &HashMap<String, i64>,
+        now: DateTime<Utc>,
+    ) -> Vec<FundingRate> {
+        let mut rates = Vec::new();
+        for (symbol, (sum, n)) in &self.samples {
+            let (Some(&mark), Some(&idx)) = (marks.get(symbol), index.get(symbol)) else { continue };
+            let Some(rate) = self.estimate(cfg, symbol) else { continue };
+            rates.push(FundingRate {
+                symbol: symbol.clone(),
+                premium: sum / *n as f64,
+                rate,
+                mark,
+                index: idx,
+                timestamp: now,
+            });
+        }
+        rates
+    }
+
+    /// Closes the interval: fixes each sampled symbol's rate and starts
+    /// sampling afresh.
+    pub fn close(
+        &mut self,
+        cfg: &FundingConfig,
+        marks: &HashMap<String, i64>,
+        index: &HashMap<String, i64>,
+        now: DateTime<Utc>,
+    ) -> Vec<FundingRate> {
+        let rates = self.estimates(cfg, marks, index, now);
+        self.samples.clear();
+        for r in &rates {
+            self.last.insert(r.symbol.clone(), r.clone());
+        }
+        rates
+    }
+}
+
+/// Pays each open position its funding at `rates`: into its margin if
+/// isolated, or its account's collateral if cross.
+pub fn settle(
+    positions: &mut [Position],
+    accounts: &mut Accounts,
+    rates: &[FundingRate],
+    now: DateTime<Utc>,
+) -> Vec<FundingPayment> {
+    let mut payments = Vec::new();
+    for pos in positions.iter_mut().filter(|p| p.open && p.size > 0) {
+        let Some(r) = rates.iter().find(|r| r.symbol == pos.symbol) else { continue };
+        let amount = payment(pos, r.mark, r.rate);
+        if amount == 0 { continue; }
+
+        match pos.margin_mode {
+            MarginMode::Isolated => pos.margin = pos.margin.saturating_add(amount),
+            MarginMode::Cross => accounts.credit(&pos.owner, amount),
+        }
+        payments.push(FundingPayment {
+            id: Uuid::new_v4(),
+            position_id: pos.id,
+            position_owner: pos.owner.clone(),
+            symbol: pos.symbol.clone(),
+            rate: r.rate,
+            amount,
+            timestamp: now,
+        });
+    }
+    payments
+}
+
+/// Samples premiums and settles funding every `interval_secs`.
+pub struct FundingEngine {
+    state: Arc<EngineState>,
+}
+
+impl FundingEngine {
+    pub fn new(state: Arc<EngineState>) -> Self { Self { state } }
+
+    pub async fn run(self) {
+        let cfg = &self.state.config.funding;
+        if !cfg.enabled { return; }
+        self.start().await;
+
+        loop {
+            self.state.clock.sleep(Duration::from_millis(cfg.sample_ms)).await;
+            self.tick().await;
+        }
+    }
+
+    /// Schedules the first settlement one interval from now.
+    pub async fn start(&self) {
+        let now = self.state.clock.now();
+        let interval = ChronoDuration::seconds(self.state.config.funding.interval_secs as i64);
+        self.state.funding.lock().await.next_settlement = Some(now + interval);
+    }
+
+    /// Takes a premium sample, and settles if the interval is up.
+    pub async fn tick(&self) {
+        let now = self.state.clock.now();
+        let cfg = &self.state.config.funding;
+        let interval = ChronoDuration::seconds(cfg.interval_secs as i64);
+        let marks = self.state.oracle.snapshot().await;
+        let index = self.state.oracle.index_snapshot().await;
+        let rates = {
+            let mut book = self.state.funding.lock().await;
+            book.sample(&marks, &index);
+            if book.next_settlement.is_some_and(|t| now < t) { return; }
+            book.next_settlement = Some(now + interval);
+            book.close(cfg, &marks, &index, now)
+        };
+        if rates.is_empty() { return; }
+        self.settle(&rates, &marks, now).await;
+    }
+
+    async fn settle(&self, rates: &[FundingRate], marks: &HashMap<String, i64>, now: DateTime<Utc>) {
+        // shadow mode records the rates but moves no margin
+        let payments = if self.state.config.shadow.enabled { Vec::new() } else {
+            let mut positions = self.state.positions.lock().await;
+            let mut accounts = self.state.accounts.lock().await;
+            let payments = settle(&mut positions, &mut accounts, rates, now);
+
+            // funding alone can tip a position under maintenance
+            for p in &payments {
+                let Some(pos) = positions.iter().find(|x| x.id == p.position_id) else { continue };
+                let Some(&mark) = marks.get(&pos.symbol) else { continue };
+                let view = accounts.view(pos, &positions, marks);
+                let Some(ratio) = risk::margin_ratio(&view, mark) else { continue };
+                if p.amount < 0 && ratio < risk::maintenance_margin(pos.leverage) {
+                    warn!("Funding of {} left pos {} below maintenance (ratio {:.6})", p.amount, pos.id, ratio);
+                }
+            }
+            payments
+        };
+
+        for r in rates {
+            info!("Funding {}: rate {:.6} (premium {:.6}, mark {}, index {})", r.symbol, r.rate, r.premium, r.mark, r.index);
+            let query = sqlx::query(
+                "INSERT INTO funding_rates (id, symbol, premium, rate, mark, index_price, created_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)"
+                )
+                .bind(Uuid::new_v4())
+                .bind(&r.symbol)
+                .bind(r.premium)
+                .bind(r.rate)
+                .bind(r.mark)
+                .bind(r.index)
+                .bind(r.timestamp);
+            let res = self.state.db.execute(query).await;
+            if let Err(e) = res {
+                error!("DB insert failed (funding rate): {:?}", e);
+            }
+        }
+
+        for p in &payments {
+            let query = sqlx::query(
+                "INSERT INTO funding_payments (id, position_id, position_owner, symbol, rate, amount, created_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)"
+                )
+                .bind(p.id)
+                .bind(p.position_id)
+                .bind(&p.position_owner)
+                .bind(&p.symbol)
+                .bind(p.rate)
+                .bind(p.amount)
+                .bind(p.timestamp);
+            let res = self.state.db.execute(query).await;
+            if let Err(e) = res {
+                error!("DB insert failed (funding payment): {:?}", e);
+            }
+        }
+
+        let _ = self.state.event_tx.send(EngineEvent::Funding(FundingEvent { rates: rates.to_vec(), payments }));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::engine::config::{InsuranceConfig, MarketInsuranceConfig};
+use crate::engine::execution::{self, ExecutionVenue};
+use crate::engine::models::{InsuranceFund, Position};
+
+/// The shared insurance fund plus any isolated per-market sub-funds.
+/// Markets without a sub-fund are backed by `shared` directly.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InsuranceFunds {
+    pub shared: InsuranceFund,
+    pub markets: BTreeMap<String, InsuranceFund>,
+    /// Positions the fund took over from auctions nobody won.
+    #[serde(default)]
+    pub positions: Vec<Position>,
+    #[serde(skip)]
+    rules: BTreeMap<String, MarketInsuranceConfig>,
+}
+
+/// Part of a book position closed through the venue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BookClose {
+    pub position_id: Uuid,
+    pub symbol: String,
+    pub size: i64,
+    pub price: i64,
+    pub pnl: i64,
+}
+
+fn fund(balance: i64) -> InsuranceFund {
+    InsuranceFund { balance, total_contributions: balance, total_bad_debt_covered: 0, shared_drawn: 0 }
+}
+
+/// What an isolated sub-fund may still draw from a shared fund holding `shared`.
+fn shared_allowance(shared: i64, fund: &InsuranceFund, rule: &MarketInsuranceConfig) -> i64 {
+    if !rule.fallback_to_shared { return 0; }
+    match rule.max_shared_draw {
+        Some(cap) => shared.min((cap - fund.shared_drawn).max(0)),
+        None => shared,
+    }
+}
+
+impl InsuranceFunds {
+    pub fn from_config(cfg: &InsuranceConfig) -> Self {
+        Self {
+            shared: fund(cfg.shared_balance),
+            markets: cfg.markets.iter().map(|(s, m)| (s.clone(), fund(m.initial_balance))).collect(),
+            positions: Vec::new(),
+            rules: cfg.markets.clone().into_iter().collect(),
+        }
+    }
+
+    /// Whether `symbol` is backed by an isolated sub-fund.
+    fn isolated(&self, symbol: &str) -> bool {
+        self.markets.contains_key(symbol) && self.rules.contains_key(symbol)
+    }
+
+    /// Unrealized PnL at `marks` of the book positions in `symbol`, or of
+    /// those the shared fund backs if `None`.
+    fn book_pnl(&self, symbol: Option<&str>, marks: &HashMap<String, i64>) -> i64 {
+        let pnl: i128 = self.positions
+            .iter()
+            .filter(|p| p.open && symbol.map_or(!self.isolated(&p.symbol), |s| p.symbol == s))
+            .filter_map(|p| marks.get(&p.symbol).map(|m| p.unrealized_pnl(*m)))
+            .sum();
+        pnl as i64
+    }
+
+    /// Cash a fund can draw on: its balance, less any unrealized loss on
+    /// the book positions it backs.
+    fn drawable(balance: i64, book_pnl: i64) -> i64 {
+        balance.saturating_add(book_pnl.min(0)).max(0)
+    }
+
+    /// Every fund's balance plus its book at `marks`.
+    pub fn total_balance(&self, marks: &HashMap<String, i64>) -> i64 {
+        let book: i128 = self.positions
+            .iter()
+            .filter(|p| p.open)
+            .filter_map(|p| marks.get(&p.symbol).map(|m| p.unrealized_pnl(*m)))
+            .sum();
+        self.markets.values().fold(self.shared.balance, |acc, f| acc.saturating_add(f.balance)).saturating_add(book as i64)
+    }
+
+    /// How much could be drawn right now to cover a deficit in `symbol`.
+    pub fn available(&self, symbol: &str, marks: &HashMap<String, i64>) -> i64 {
+        let shared = Self::drawable(self.shared.balance, self.book_pnl(None, marks));
+        match (self.markets.get(symbol), self.rules.get(symbol)) {
+            (Some(f), Some(r)) => Self::drawable(f.balance, self.book_pnl(Some(symbol), marks))
+                .saturating_add(shared_allowance(shared, f, r)),
+            _ => shared,
+        }
+    }
+
+    /// Takes `size` of `source` onto the fund's book at `price`.
+    pub fn take_over(&mut self, source: &Position, size: i64, price: i64) {
+        self.positions.push(source.transferred("insurance_fund", size, price));
+    }
+
+    /// Closes what `venue` will take of the fund's book at `marks`,
+    /// settling each close's PnL into the fund backing its market.
+    pub fn unwind(&mut self, venue: &dyn ExecutionVenue, marks: &HashMap<String, i64>) -> Vec<BookClose> {
+        let mut closes = Vec::new();
+        for i in 0..self.positions.len() {
+            let pos = &mut self.positions[i];
+            let Some(&mark) = marks.get(&pos.symbol) else { continue };
+            if !pos.open { continue; }
+            let fill = execution::close_position(venue, pos, mark);
+            if fill.filled <= 0 { continue; }
+
+            let pnl = std::mem::take(&mut pos.margin);
+            let (position_id, symbol) = (pos.id, pos.symbol.clone());
+            match self.markets.get_mut(&symbol).filter(|_| self.rules.contains_key(&symbol)) {
+                Some(f) => f.balance = f.balance.saturating_add(pnl),
+                None => self.shared.balance = self.shared.balance.saturating_add(pnl),
+            }
+            closes.push(BookClose { position_id, symbol, size: fill.filled, price: fill.vwap, pnl });
+        }
+        self.positions.retain(|p| p.open);
+        closes
+    }
+
+    /// Adds a contribution from `symbol`. Isolated funds sweep anything above
+    /// their `sweep_above` threshold into the shared fund.
+    pub fn credit(&mut self, symbol: &str, amount: i64) {
+        let Some(rule) = self.rules.get(symbol) else {
+            self.shared.balance = self.shared.balance.saturating_add(amount);
+            self.shared.total_contributions = self.shared.total_contributions.saturating_add(amount);
+            return;
+        };
+        let sweep_above = rule.sweep_above;
+
+        let f = self.markets.entry(symbol.to_string()).or_insert_with(|| fund(0));
+        f.balance = f.balance.saturating_add(amount);
+        f.total_contributions = f.total_contributions.saturating_add(amount);
+
+        if let Some(cap) = sweep_above {
+            let excess = f.balance - cap;
+            if excess > 0 {
+                f.balance -= excess;
+                self.shared.balance = self.shared.balance.saturating_add(excess);
+                self.shared.total_contributions = self.shared.total_contributions.saturating_add(excess);
+            }
+        }
+    }
+
+    /// Covers as much of `deficit` as the rules allow, isolated fund first,
+    /// and returns the amount covered. Unrealized losses on the fund's book
+    /// at `marks` aren't drawn on.
+    pub fn cover(&mut self, symbol: &str, deficit: i64, marks: &HashMap<String, i64>) -> i64 {
+        let shared = Self::drawable(self.shared.balance, self.book_pnl(None, marks));
+        let own_pnl = self.book_pnl(Some(symbol), marks);
+        let (covered, take) = match (self.markets.get_mut(symbol), self.rules.get(symbol)) {
+            (Some(f), Some(rule)) => {
+                let covered = deficit.min(Self::drawable(f.balance, own_pnl)).max(0);
+                f.balance -= covered;
+                f.total_bad_debt_covered = f.total_bad_debt_covered.saturating_add(covered);
+                let take = (deficit - covered).min(shared_allowance(shared, f, rule)).max(0);
+                f.shared_drawn = f.shared_drawn.saturating_add(take);
+                (covered, take)
+            }
+            _ => (0, deficit.min(shared).max(0)),
+        };
+
+        self.shared.balance -= take;
+        self.shared.total_bad_debt_covered = self.shared.total_bad_debt_covered.saturating_add(take);
+        covered + take
+    }
+}
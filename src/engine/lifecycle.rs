@@ -0,0 +1,76 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::engine::config::MarketConfig;
+use crate::engine::models::{LiquidationStage, LiquidationStatus};
+
+impl LiquidationStatus {
+    /// Moves between healthy / margin call / partially liquidated based on
+    /// the current `ratio`. Cooling down and fully liquidated positions only
+    /// leave those stages once their cooldown has expired.
+    /// Returns true if the position has just become liquidatable.
+    pub fn observe(&mut self, now: DateTime<Utc>, ratio: f64, maintenance: f64, target: f64) -> bool {
+        let newly_liquidatable = ratio < maintenance && self.liquidatable_since.is_none();
+        if ratio < maintenance {
+            self.liquidatable_since.get_or_insert(now);
+        } else {
+            self.liquidatable_since = None;
+        }
+
+        match self.stage {
+            LiquidationStage::FullyLiquidated => return false,
+            LiquidationStage::CoolingDown if self.cooling_down(now) => return newly_liquidatable,
+            LiquidationStage::CoolingDown => self.stage = LiquidationStage::PartiallyLiquidated,
+            _ => {}
+        }
+
+        self.stage = if ratio >= target {
+            LiquidationStage::Healthy
+        } else if ratio >= maintenance || self.stage == LiquidationStage::Healthy {
+            LiquidationStage::MarginCall
+        } else {
+            self.stage
+        };
+        newly_liquidatable
+    }
+
+    /// Still inside the window where only external liquidators may act.
+    pub fn in_grace(&self, now: DateTime<Utc>, grace_ms: u64) -> bool {
+        self.liquidatable_since
+            .is_some_and(|t| now - t < Duration::milliseconds(grace_ms as i64))
+    }
+
+    pub fn cooling_down(&self, now: DateTime<Utc>) -> bool {
+        self.cooldown_until.is_some_and(|t| now < t)
+    }
+
+    /// Whether another partial step is allowed right now under the market's
+    /// cooldown and steps-per-window limits.
+    pub fn can_step(&self, now: DateTime<Utc>, market: &MarketConfig) -> bool {
+        if self.cooling_down(now) { return false; }
+        match self.window_started_at {
+            Some(start) if now - start < Duration::milliseconds(market.step_window_ms as i64) => {
+                self.steps_in_window < market.max_steps_per_window
+            }
+            _ => true,
+        }
+    }
+
+    /// Books a liquidation step taken at `now`.
+    pub fn record_step(&mut self, now: DateTime<Utc>, market: &MarketConfig, closed: bool) {
+        let window_open = self
+            .window_started_at
+            .is_some_and(|start| now - start < Duration::milliseconds(market.step_window_ms as i64));
+        if !window_open {
+            self.window_started_at = Some(now);
+            self.steps_in_window = 0;
+        }
+        self.steps_in_window += 1;
+
+        if closed {
+            self.stage = LiquidationStage::FullyLiquidated;
+            self.cooldown_until = None;
+        } else {
+            self.stage = LiquidationStage::CoolingDown;
+            self.cooldown_until = Some(now + Duration::milliseconds(market.cooldown_ms as i64));
+        }
+    }
+}
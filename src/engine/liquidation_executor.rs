@@ -1,14 +1,65 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
-use log::{info, error};
-use sqlx::PgPool;
-use crate::engine::{EngineState, models::{LiquidationRecord, LiquidationEvent}};
-use chrono::Utc;
+use std::time::Duration;
+use log::{info, warn, error};
+use crate::engine::auction::{AuctionEvent, AuctionStatus};
+use crate::engine::config::EngineConfig;
+use crate::engine::controls::{Controls, EngineMode};
+use crate::engine::store::Store;
+use crate::engine::throttle::Backlogged;
+use crate::engine::waterfall::{Liquidation, Mode, StageContext};
+use crate::engine::{EngineState, accounts, backstop, risk, models::{
+    CollateralSaleEvent, EngineEvent, LiquidatableEvent, LiquidationKind, LiquidationRecord, LiquidationEvent, LiquidationStage, MarginMode, Position, SocializedLossEvent, SocializedLossRecord,
+}};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+/// Why a liquidator's takeover request was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TakeoverError {
+    NotFound,
+    NoMarkPrice,
+    NotLiquidatable,
+    CoolingDown,
+    InAuction,
+    InvalidSize { max: i64 },
+    /// Operators have turned liquidations off for the position's symbol.
+    Paused,
+    /// The engine is running in shadow mode and closes nothing.
+    Shadow,
+}
+
+impl std::fmt::Display for TakeoverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TakeoverError::NotFound => write!(f, "no such open position"),
+            TakeoverError::NoMarkPrice => write!(f, "no mark price"),
+            TakeoverError::NotLiquidatable => write!(f, "position is not liquidatable"),
+            TakeoverError::CoolingDown => write!(f, "position is cooling down"),
+            TakeoverError::InAuction => write!(f, "position is being auctioned"),
+            TakeoverError::InvalidSize { max } => write!(f, "size must be between 1 and {}", max),
+            TakeoverError::Paused => write!(f, "liquidations are paused"),
+            TakeoverError::Shadow => write!(f, "engine is in shadow mode"),
+        }
+    }
+}
+
+/// Largest size one liquidation step may close: the smallest cut that
+/// restores the market's target ratio, within its per-step limits.
+pub fn step_size(cfg: &EngineConfig, pos: &Position, mark: i64) -> i64 {
+    let market = cfg.market(&pos.symbol);
+    let target = market.target_ratio(pos.leverage, risk::maintenance_margin(pos.leverage));
+    let wanted = risk::reduction_to_target(pos, mark, target, cfg.penalty.rate_bps);
+    market.cap_step(wanted, pos.size)
+}
+
+/// Runs liquidation passes and takeovers. Everything it works on, the
+/// waterfall and throttle included, lives on the shared `EngineState`, so
+/// any handle on it acts exactly as the one running passes.
 pub struct LiquidationExecutor {
-    state: Arc<Eng neState>,
-    db: PgPool,
+    state: Arc<EngineState>,
+    db: Store,
 }
 
 impl LiquidationExecutor {
@@ -17,126 +68,568 @@ impl LiquidationExecutor {
     }
 
     pub async fn run(self) {
+        info!("Liquidation waterfall: {:?}", self.state.waterfall.kinds());
         loop {
-            sleep(Duration::from_millis(1200)).await;
+            self.state.clock.sleep(Duration::from_millis(1200)).await;
+            self.pass().await;
+        }
+    }
 
+    /// One look at every open position: settles auctions and steps
+    /// whatever is liquidatable.
+    pub async fn pass(&self) {
+        let now = self.state.clock.now();
+        let controls = self.state.controls.lock().await.clone();
+        if controls.mode == EngineMode::Paused { return; }
+        if self.state.config.shadow.enabled {
+            self.shadow_pass(now).await;
+            return;
+        }
 
-            let mut positions = self.state.positions.lock().await;
-            for pos in positions.iter_mut() { 
-                if !pos.open { continue; }
+        let mut positions = self.state.positions.lock().await;
+        self.run_auctions(&controls, &mut positions, now).await;
 
-                if let Some(mark) = self.state.oracle.get_mark_price(&pos.symbol).await {
-                    // compute PnL
-                    let unrealized = if pos.is_long {
-                        (pos.size as i128) * (mark as i128 - pos.entry_price as i128)
-                    } else {
-                        (pos.size as i128) * (pos.entry_price as i128 - mark as i128)
-                    };
+        let marks = self.state.oracle.snapshot().await;
+        // cross accounts get one cut per pass
+        let mut cut_accounts = HashSet::new();
+        let mut candidates = Vec::new();
 
-                    let pos_value = (pos.size as i128) * (mark as i128);
-                    if pos_value <= 0 { continue; }
+        for i in Self::liquidation_order(&positions, &marks) {
+            if !positions[i].open { continue; }
 
-                    let margin_ratio = (pos.margin as i128 + unrealized) as f64 / pos_value as f64;
-                    let mm = maintenance_margin(pos.leverage);
+            if let Some(&mark) = marks.get(&positions[i].symbol) {
+                let view = self.state.accounts.lock().await.view(&positions[i], &positions, &marks);
+                let Some(margin_ratio) = risk::margin_ratio(&view, mark) else { continue };
+                let pos = &mut positions[i];
+                let mm = risk::maintenance_margin(pos.leverage);
+                let market = self.state.config.market(&pos.symbol);
+                let target = market.target_ratio(pos.leverage, mm);
 
-                    if margin_ratio < mm {
-                        // Partial liquidation: reduce by 50% (min 1)
-                        let reduction = ((pos.size as i64) / 2).max(1) as i64;
+                let prev_stage = pos.liquidation.stage;
+                if pos.liquidation.observe(now, margin_ratio, mm, target) {
+                    self.notify_liquidatable(&view, mark, margin_ratio);
+                }
 
-                        // compute liquidated value and reward (2.5%)
-                        let liquidated_value = (reduction as i128) * (mark as i128);
-                        let reward = ((liquidated_value as i128 * 25) / 1000) as i64; // 2.5%
+                // bankrupt positions skip the cooldown, the grace period and
+                // any auction they're in; everything else waits its turn
+                let deferred = margin_ratio >= 0.0
+                    && (!pos.liquidation.can_step(now, market)
+                        || pos.liquidation.in_grace(now, self.state.config.liquidators.grace_ms));
 
-                        // store 
-                        let margin_before = (pos.margin as i128 + unrealized) as i64;
+                let in_auction = self.in_auction(pos.id).await;
+                let cross = pos.margin_mode == MarginMode::Cross;
+                let account_cut = cross && cut_accounts.contains(&pos.owner);
 
-                        // apply reduction
-                        pos.size = (pos.size as i64 - reduction) as i64;
-                        if pos.size <= 0 {
-                            pos.open = false;
+                if margin_ratio < mm && !controls.liquidates(&pos.symbol) {
+                    info!("Would liquidate pos {} (ratio {:.6}) but liquidations on {} are off", pos.id, margin_ratio, pos.symbol);
+                } else if margin_ratio < mm && !deferred && (!in_auction || margin_ratio < 0.0) && !account_cut {
+                    if cross {
+                        // sell collateral first; positions are cut on a later pass if that wasn't enough
+                        let owner = pos.owner.clone();
+                        cut_accounts.insert(owner.clone());
+                        if self.sell_collateral(&positions, &owner, &marks, now).await {
+                            continue;
                         }
+                    }
+                    candidates.push((i, margin_ratio, prev_stage));
+                    continue;
+                }
 
-                        // compute new unrealized & margin_after
-                        let new_unrealized = if pos.is_long {
-                            (pos.size as i128) * (mark as i128 - pos.entry_price as i128)
-                        } else {
-                            (pos.size as i128) * (pos.entry_price as i128 - mark as i128)
-                        };
-                        let margin_after = (pos.margin as i128 + new_unrealized) as i64;
-
-                        // liquidation record
-                        let record = LiquidationRecord {
-                            id: Uuid::new_v4(),
-                            position_id: pos.id,
-                            position_owner: pos.owner.clone(),
-                            liquidator: "executor".into(),
-                            symbol: pos.symbol.clone(),
-                            liquidated_size: reduction,
-                            liquidation_price: mark,
-                            margin_before,
-                            margin_after,
-                            liquidator_reward: reward,
-                            bad_debt: 0,
-                            timestamp: Utc::now(),
-                        };
-
-                        // persist
-                        if let Err(e) = self.insert_record(&record).await {
-                            error!("DB insert failed: {:?}", e);
-                        } else {
-                            let _ = self.state.event_tx.send(LiquidationEvent { record: record.clone() });
-                            info!("Executed partial liquidation for pos {} reduction {}", pos.id, reduction);
-                        }
+                if positions[i].liquidation.stage != prev_stage {
+                    self.save_status(&positions[i]).await;
+                }
+            }
+        }
 
-                        // if position now zero or margin after negative full liquidation handling
-                        if pos.size <= 0 || margin_after < 0 {
-                            // compute bad debt if any
-                            let bd = if margin_after < 0 {
-                                let deficit = (-margin_after) as i64;
-                                let mut ins = self.state.insurance.lock().await;
-                                let cover = deficit.min(ins.balance);
-                                ins.balance = ins.balance.saturating_sub(cover);
-                                ins.total_bad_debt_covered = ins.total_bad_debt_covered.saturating_add(cover);
-                                cover
-                            } else { 0i64 };
-
-                            let record_full = LiquidationRecord {
-                                id: Uuid::new_v4(),
-                                position_id: pos.id,
-                                position_owner: pos.owner.clone(),
-                                liquidator: "executor".into(),
-                                symbol: pos.symbol.clone(),
-                                liquidated_size: pos.size,
-                                liquidation_price: mark,
-                                margin_before: margin_after,
-                                margin_after: 0,
-                                liquidator_reward: 0,
-                                bad_debt: bd,
-                                timestamp: Utc::now(),
-                            };
-
-                            if let Err(e) = self.insert_record(&record_full).await {
-                                error!("DB insert failed (full): {:?}", e);
-                            } else {
-                                let _ = self.state.event_tx.send(LiquidationEvent { record: record_full.clone() });
-                                info!("Executed full liquidation for pos {} bad_debt {}", pos.id, bd);
-                            }
-
-                            pos.open = false;
-                        }
-                    }
+        self.cut(&mut positions, candidates, now).await;
+    }
+
+    /// A pass that only records what it would liquidate. It works on a
+    /// copy of the positions and never touches accounts, funds or the
+    /// order book; collateral sales, auctions and the throttle aren't
+    /// modelled.
+    async fn shadow_pass(&self, now: DateTime<Utc>) {
+        let positions = self.state.positions.lock().await.clone();
+        let marks = self.state.oracle.snapshot().await;
+        let mut cut_accounts = HashSet::new();
+        let mut decisions = Vec::new();
+        {
+            let accounts = self.state.accounts.lock().await;
+            let mut shadow = self.state.shadow.lock().await;
+            for i in Self::liquidation_order(&positions, &marks) {
+                let pos = &positions[i];
+                if !pos.open { continue; }
+                let Some(&mark) = marks.get(&pos.symbol) else { continue };
+                let view = accounts.view(pos, &positions, &marks);
+                let cross = pos.margin_mode == MarginMode::Cross;
+                let account_cut = cross && cut_accounts.contains(&pos.owner);
+                if let Some(decision) = shadow.decide(&self.state.config, &view, mark, now, account_cut) {
+                    if cross { cut_accounts.insert(pos.owner.clone()); }
+                    shadow.record(&decision);
+                    decisions.push(decision);
+                }
+            }
+            shadow.end_pass(&positions);
+        }
+
+        for d in &decisions {
+            info!("Shadow: would liquidate {} of pos {} on {} at {} (ratio {:.6}{})",
+                d.size, d.position_id, d.symbol, d.mark, d.margin_ratio, if d.bankrupt { ", bankrupt" } else { "" });
+            let query = sqlx::query(
+                "INSERT INTO shadow_liquidations (
+                        id, position_id, position_owner, symbol, is_long, margin_mode,
+                        position_size, size, mark, notional, margin_ratio,
+                        maintenance_margin, target_ratio, bankrupt, created_at
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)"
+                )
+                .bind(d.id)
+                .bind(d.position_id)
+                .bind(&d.position_owner)
+                .bind(&d.symbol)
+                .bind(d.is_long)
+                .bind(d.margin_mode.as_str())
+                .bind(d.position_size)
+                .bind(d.size)
+                .bind(d.mark)
+                .bind(d.notional)
+                .bind(d.margin_ratio)
+                .bind(d.maintenance_margin)
+                .bind(d.target_ratio)
+                .bind(d.bankrupt)
+                .bind(d.timestamp);
+            let res = self.db.execute(query).await;
+            if let Err(e) = res {
+                error!("DB insert failed (shadow liquidation): {:?}", e);
+            }
+        }
+    }
+
+    /// Steps each candidate (index, margin ratio, stage before this pass),
+    /// most severe first, as far as the throttle's notional budget allows.
+    /// The rest wait in the throttle's backlog for a later pass, except
+    /// bankrupt positions, which are cut whatever the budget.
+    async fn cut(&self, positions: &mut [Position], mut candidates: Vec<(usize, f64, LiquidationStage)>, now: DateTime<Utc>) {
+        let cfg = &self.state.config.throttle;
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let mut backlog = Vec::new();
+
+        for (i, margin_ratio, prev_stage) in candidates {
+            let marks = self.state.oracle.snapshot().await;
+            let Some(&mark) = marks.get(&positions[i].symbol) else { continue };
+            let view = self.state.accounts.lock().await.view(&positions[i], positions, &marks);
+
+            let wanted = self.step_size(&view, mark);
+            let allowance = self.state.throttle.lock().await.allowance(cfg, &view.symbol, now);
+            // waiting only deepens a bankrupt position's deficit
+            let size = if margin_ratio < 0.0 { wanted } else { wanted.min(allowance / mark.max(1)) };
+            if size > 0 {
+                let booked = self.step(positions, i, size, mark, now, |ctx, l| self.state.waterfall.run(ctx, l)).await;
+                // a bankrupt remainder is closed out in full, budget or not
+                let notional: i128 = booked.iter().map(|r| r.liquidated_size as i128 * r.liquidation_price as i128).sum();
+                self.state.throttle.lock().await.record(&view.symbol, notional as i64, now);
+            } else {
+                backlog.push(Backlogged {
+                    position_id: view.id,
+                    position_owner: view.owner.clone(),
+                    symbol: view.symbol.clone(),
+                    margin_ratio,
+                    notional: (wanted as i128 * mark as i128) as i64,
+                    queued_since: now,
+                });
+            }
+
+            if positions[i].liquidation.stage != prev_stage {
+                self.save_status(&positions[i]).await;
+            }
+        }
+
+        if !backlog.is_empty() {
+            warn!("Liquidation throttle: {} liquidatable positions queued", backlog.len());
+        }
+        self.state.throttle.lock().await.queue(backlog);
+    }
+
+    /// Position indices in the order a pass looks at them: an account's
+    /// cross positions biggest maintenance requirement first, so that's the
+    /// one its cut goes to.
+    fn liquidation_order(positions: &[Position], marks: &HashMap<String, i64>) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..positions.len()).collect();
+        order.sort_by_key(|&i| {
+            let p = &positions[i];
+            let req = match (p.margin_mode, marks.get(&p.symbol)) {
+                (MarginMode::Cross, Some(mark)) => accounts::requirement(p, *mark),
+                _ => 0,
+            };
+            Reverse(req)
+        });
+        order
+    }
+
+    fn step_size(&self, pos: &Position, mark: i64) -> i64 {
+        step_size(&self.state.config, pos, mark)
+    }
+
+    async fn in_auction(&self, position_id: Uuid) -> bool {
+        self.state.auctions.lock().await
+            .iter()
+            .any(|a| a.is_live() && a.position_id == position_id)
+    }
+
+    fn notify_liquidatable(&self, pos: &Position, mark: i64, margin_ratio: f64) {
+        let _ = self.state.event_tx.send(EngineEvent::Liquidatable(LiquidatableEvent {
+            position_id: pos.id,
+            position_owner: pos.owner.clone(),
+            symbol: pos.symbol.clone(),
+            is_long: pos.is_long,
+            size: pos.size,
+            mark,
+            margin_ratio,
+            max_takeover_size: self.step_size(pos, mark),
+        }));
+    }
+
+    /// Runs `apply` on a step of `size` of `positions[i]` and books it, then
+    /// sends the position down the close-out waterfall if the step left
+    /// nothing or negative equity. Returns the records booked.
+    async fn step(
+        &self,
+        positions: &mut [Position],
+        i: usize,
+        size: i64,
+        mark: i64,
+        now: DateTime<Utc>,
+        apply: impl FnOnce(&mut StageContext, &mut Liquidation),
+    ) -> Vec<LiquidationRecord> {
+        self.step_then(positions, i, size, mark, now, apply, |ctx, l| self.state.waterfall.run(ctx, l)).await
+    }
+
+    /// `step`, closing out with `close_out` instead of the waterfall.
+    #[allow(clippy::too_many_arguments)]
+    async fn step_then(
+        &self,
+        positions: &mut [Position],
+        i: usize,
+        size: i64,
+        mark: i64,
+        now: DateTime<Utc>,
+        apply: impl FnOnce(&mut StageContext, &mut Liquidation),
+        close_out: impl FnOnce(&mut StageContext, &mut Liquidation),
+    ) -> Vec<LiquidationRecord> {
+        self.lend(positions, i).await;
+        let mut liq = Liquidation::step(positions[i].clone(), size, mark, now);
+        self.run_stages(positions, &mut liq, apply).await;
+        let closed = liq.closed() > 0;
+        let mut booked = self.book(positions, i, liq).await;
+
+        // a bankrupt position is closed out even if the venue took none of
+        // the step, so the later stages get what it couldn't absorb
+        let marks = self.state.oracle.snapshot().await;
+        let view = self.state.accounts.lock().await.view(&positions[i], positions, &marks);
+        let bankrupt = view.open && (view.margin as i128 + view.unrealized_pnl(mark)) < 0;
+        if (closed && view.size <= 0) || bankrupt {
+            self.lend(positions, i).await;
+            let mut liq = Liquidation::close_out(positions[i].clone(), mark, now);
+            self.run_stages(positions, &mut liq, close_out).await;
+            booked.extend(self.book(positions, i, liq).await);
+        }
+        booked
+    }
+
+    /// Sells an account's non-cash collateral towards its maintenance
+    /// requirement. Returns whether anything was sold.
+    async fn sell_collateral(&self, positions: &[Position], owner: &str, marks: &HashMap<String, i64>, now: DateTime<Utc>) -> bool {
+        let sales = self.state.accounts.lock().await
+            .sell_collateral(owner, positions, marks, self.state.venue.as_ref(), now);
+
+        for sale in &sales {
+            info!("Sold {} {} of {}'s collateral at {} for {}", sale.quantity, sale.asset, owner, sale.price, sale.proceeds);
+            let query = sqlx::query(
+                "INSERT INTO collateral_sales (id, owner, asset, quantity, price, proceeds, created_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)"
+                )
+                .bind(sale.id)
+                .bind(&sale.owner)
+                .bind(&sale.asset)
+                .bind(sale.quantity)
+                .bind(sale.price)
+                .bind(sale.proceeds)
+                .bind(sale.timestamp);
+            let res = self.db.execute(query).await;
+            if let Err(e) = res {
+                error!("DB insert failed (collateral sale): {:?}", e);
+            }
+            let _ = self.state.event_tx.send(EngineEvent::CollateralSale(CollateralSaleEvent { sale: sale.clone() }));
+        }
+        !sales.is_empty()
+    }
+
+    /// Backs a cross position with its share of account collateral for the
+    /// length of a liquidation pass; `book` settles it back.
+    async fn lend(&self, positions: &mut [Position], i: usize) {
+        let marks = self.state.oracle.snapshot().await;
+        self.state.accounts.lock().await.lend(positions, i, &marks);
+    }
+
+    /// Takes the locks the stages need and runs `apply` under them.
+    async fn run_stages(
+        &self,
+        positions: &mut [Position],
+        liq: &mut Liquidation,
+        apply: impl FnOnce(&mut StageContext, &mut Liquidation),
+    ) {
+        let marks = self.state.oracle.snapshot().await;
+        let mut accounts = self.state.accounts.lock().await;
+        let mut backstop = self.state.backstop.lock().await;
+        let mut insurance = self.state.insurance.lock().await;
+        let mut ctx = StageContext {
+            config: &self.state.config,
+            positions,
+            accounts: &mut accounts,
+            insurance: &mut insurance,
+            backstop: &mut backstop,
+            venue: self.state.venue.as_ref(),
+            marks: &marks,
+        };
+        apply(&mut ctx, liq);
+    }
+
+    /// Writes the liquidated position back and persists / broadcasts what
+    /// the stages did to it.
+    async fn book(&self, positions: &mut [Position], i: usize, liq: Liquidation) -> Vec<LiquidationRecord> {
+        let Liquidation { position, mode, now, outcome, .. } = liq;
+        let closed = outcome.records.iter().any(|r| r.position_id == position.id);
+        positions[i] = position;
+        let pos = &mut positions[i];
+
+        let released = pos.margin;
+        self.state.accounts.lock().await.settle(pos, released);
+
+        if mode == Mode::Step {
+            if closed {
+                pos.liquidation.record_step(now, self.state.config.market(&pos.symbol), !pos.open);
+            } else if outcome.auction.is_none() {
+                warn!("No liquidity to liquidate pos {} on {}", pos.id, pos.symbol);
+            }
+        }
+        let (pos_id, symbol) = (pos.id, pos.symbol.clone());
+
+        for fill in &outcome.backstop {
+            backstop::log_ledger(&self.db, "takeover", "backstop", fill.fee, 0, Some(pos_id), fill.size, fill.price, now).await;
+            info!("Backstop took over {} of pos {} at {} for fee {}", fill.size, pos_id, fill.price, fill.fee);
+        }
+        for amount in &outcome.contributions {
+            self.log_contribution(pos_id, &symbol, *amount).await;
+        }
+        if outcome.fees > 0 {
+            self.state.fees.lock().await.collect_liquidation(outcome.fees);
+        }
+        for rec in &outcome.records {
+            info!("{} liquidation of {} of pos {} at {} by {}",
+                rec.kind.as_str(), rec.liquidated_size, rec.position_id, rec.liquidation_price, rec.liquidator);
+            if matches!(rec.kind, LiquidationKind::Partial | LiquidationKind::Auction) {
+                self.reward_liquidator(&rec.liquidator, rec.liquidator_reward).await;
+            }
+            self.publish(rec.clone()).await;
+        }
+        for rec in outcome.haircuts {
+            if let Err(e) = self.insert_socialized_loss(&rec).await {
+                error!("DB insert failed (socialized loss): {:?}", e);
+            }
+            info!("Socialized loss: haircut {} from pos {} for pos {}", rec.haircut, rec.position_id, pos_id);
+            let _ = self.state.event_tx.send(EngineEvent::SocializedLoss(SocializedLossEvent { record: rec }));
+        }
+        if let Some(auction) = outcome.auction {
+            info!("Auction {} started for {} of pos {}", auction.id, auction.size, pos_id);
+            let _ = self.state.event_tx.send(EngineEvent::Auction(AuctionEvent { auction: auction.clone() }));
+            self.state.auctions.lock().await.push(auction);
+        }
+        if outcome.unabsorbed > 0 {
+            error!("Unabsorbed bad debt {} on {} from pos {}", outcome.unabsorbed, symbol, pos_id);
+        }
+
+        outcome.records.into_iter().filter(|r| r.position_id == pos_id).collect()
+    }
+
+    /// A registered liquidator takes over `size` of an open, liquidatable
+    /// position at mark, earning the liquidator share of the penalty.
+    pub async fn takeover(&self, liquidator: &str, position_id: Uuid, size: i64) -> Result<LiquidationRecord, TakeoverError> {
+        let mut positions = self.state.positions.lock().await;
+        let i = positions
+            .iter()
+            .position(|p| p.id == position_id && p.open)
+            .ok_or(TakeoverError::NotFound)?;
+        if self.state.config.shadow.enabled {
+            return Err(TakeoverError::Shadow);
+        }
+        if !self.state.controls.lock().await.liquidates(&positions[i].symbol) {
+            return Err(TakeoverError::Paused);
+        }
+        let marks = self.state.oracle.snapshot().await;
+        let mark = *marks.get(&positions[i].symbol).ok_or(TakeoverError::NoMarkPrice)?;
+        let pos = &self.state.accounts.lock().await.view(&positions[i], &positions, &marks);
+        let ratio = risk::margin_ratio(pos, mark).ok_or(TakeoverError::NotFound)?;
+        if ratio >= risk::maintenance_margin(pos.leverage) {
+            return Err(TakeoverError::NotLiquidatable);
+        }
+        let now = self.state.clock.now();
+        if ratio >= 0.0 && !pos.liquidation.can_step(now, self.state.config.market(&pos.symbol)) {
+            return Err(TakeoverError::CoolingDown);
+        }
+        if self.in_auction(pos.id).await {
+            return Err(TakeoverError::InAuction);
+        }
+        let max = self.step_size(pos, mark);
+        if size <= 0 || size > max {
+            return Err(TakeoverError::InvalidSize { max });
+        }
+
+        let booked = self.step(&mut positions, i, size, mark, now, |ctx, l| {
+            l.close(ctx, size, mark, liquidator, LiquidationKind::Partial);
+        }).await;
+        // counts against the same budget as the executor's own cuts
+        let notional: i128 = booked.iter().map(|r| r.liquidated_size as i128 * r.liquidation_price as i128).sum();
+        self.state.throttle.lock().await.record(&positions[i].symbol, notional as i64, now);
+        self.save_status(&positions[i]).await;
+        Ok(booked.into_iter().next().expect("takeover closes at least one unit"))
+    }
+
+    /// Credits a registered liquidator's account; the executor and the
+    /// insurance fund aren't accounts and are skipped.
+    async fn reward_liquidator(&self, liquidator: &str, reward: i64) {
+        let Some(acct) = self.state.liquidators.lock().await.credit(liquidator, reward) else { return };
+
+        let query = sqlx::query(
+            "INSERT INTO liquidator_accounts (name, balance, total_rewards, takeovers, registered_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (name) DO UPDATE SET
+                    balance = EXCLUDED.balance,
+                    total_rewards = EXCLUDED.total_rewards,
+                    takeovers = EXCLUDED.takeovers,
+                    updated_at = EXCLUDED.updated_at"
+            )
+            .bind(&acct.name)
+            .bind(acct.balance)
+            .bind(acct.total_rewards)
+            .bind(acct.takeovers as i64)
+            .bind(acct.registered_at)
+            .bind(self.state.clock.now());
+        let res = self.db.execute(query).await;
+        if let Err(e) = res {
+            error!("DB upsert failed (liquidator account): {:?}", e);
+        }
+    }
+
+    /// Settles accepted auctions and hands expired ones to the insurance fund.
+    async fn run_auctions(&self, controls: &Controls, positions: &mut [Position], now: DateTime<Utc>) {
+        let mut auctions = self.state.auctions.lock().await;
+
+        // auctions on paused symbols wait, neither settled nor expired
+        for auction in auctions.iter_mut().filter(|a| a.is_live() && controls.liquidates(&a.symbol)) {
+            let Some(idx) = positions.iter().position(|p| p.id == auction.position_id && p.open) else {
+                // position went away some other way
+                auction.status = AuctionStatus::Expired;
+                continue;
+            };
+            let Some(mark) = self.state.oracle.get_mark_price(&auction.symbol).await else { continue };
+
+            match auction.status {
+                AuctionStatus::Accepted => {
+                    // the distance from mark is the winner's reward
+                    let Some(bid) = auction.bids.last().cloned() else { continue };
+                    self.step(positions, idx, auction.size, mark, now, |ctx, l| {
+                        l.close(ctx, auction.size, bid.price, &bid.liquidator, LiquidationKind::Auction);
+                    }).await;
+                    auction.status = AuctionStatus::Settled;
                 }
+                AuctionStatus::Open if auction.expired(now) => {
+                    // the backstop gets first refusal, the insurance fund takes
+                    // the rest onto its book, and with it any deficit left
+                    self.step_then(positions, idx, auction.size, mark, now, |ctx, l| {
+                        l.backstop(ctx, mark, LiquidationKind::Auction);
+                        l.insurance_takeover(ctx, LiquidationKind::Auction);
+                    }, |ctx, l| {
+                        l.insurance_takeover(ctx, LiquidationKind::Full);
+                        l.finish(ctx);
+                    }).await;
+                    auction.status = AuctionStatus::Expired;
+                }
+                _ => continue,
             }
+            self.save_status(&positions[idx]).await;
+
+            info!("Auction {} {:?}", auction.id, auction.status);
+            let _ = self.state.event_tx.send(EngineEvent::Auction(AuctionEvent { auction: auction.clone() }));
+        }
+
+        // keep finished auctions around for an hour so they stay visible
+        auctions.retain(|a| a.is_live() || now - a.expires_at < chrono::Duration::hours(1));
+    }
+
+    async fn save_status(&self, pos: &Position) {
+        let st = &pos.liquidation;
+        let query = sqlx::query(
+            "INSERT INTO position_liquidation_state (
+                    position_id, position_owner, symbol, stage,
+                    steps_in_window, window_started_at, cooldown_until, updated_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (position_id) DO UPDATE SET
+                    stage = EXCLUDED.stage,
+                    steps_in_window = EXCLUDED.steps_in_window,
+                    window_started_at = EXCLUDED.window_started_at,
+                    cooldown_until = EXCLUDED.cooldown_until,
+                    updated_at = EXCLUDED.updated_at"
+            )
+            .bind(pos.id)
+            .bind(&pos.owner)
+            .bind(&pos.symbol)
+            .bind(st.stage.as_str())
+            .bind(st.steps_in_window as i32)
+            .bind(st.window_started_at)
+            .bind(st.cooldown_until)
+            .bind(self.state.clock.now());
+        let res = self.db.execute(query).await;
+        if let Err(e) = res {
+            error!("DB upsert failed (liquidation state): {:?}", e);
+        }
+        info!("Position {} is now {}", pos.id, st.stage.as_str());
+    }
+
+    /// Records the fund's share of a liquidation penalty; the stage that
+    /// charged it has already credited the fund.
+    async fn log_contribution(&self, position_id: Uuid, symbol: &str, amount: i64) {
+        let query = sqlx::query(
+            "INSERT INTO insurance_contributions (id, position_id, symbol, amount, created_at)
+                VALUES ($1, $2, $3, $4, $5)"
+            )
+            .bind(Uuid::new_v4())
+            .bind(position_id)
+            .bind(symbol)
+            .bind(amount)
+            .bind(self.state.clock.now());
+        let res = self.db.execute(query).await;
+        if let Err(e) = res {
+            error!("DB insert failed (insurance contribution): {:?}", e);
+        }
+        info!("Insurance fund credited {} from liquidation of pos {}", amount, position_id);
+    }
+
+    /// Persists a record and, if that worked, pushes it to subscribers.
+    async fn publish(&self, record: LiquidationRecord) {
+        if let Err(e) = self.insert_record(&record).await {
+            error!("DB insert failed ({}): {:?}", record.kind.as_str(), e);
+        } else {
+            let _ = self.state.event_tx.send(EngineEvent::Liquidation(LiquidationEvent { record }));
         }
     }
 
     async fn insert_record(&self, rec: &LiquidationRecord) -> Result<(), sqlx::Error> {
-        sqlx::query(
+        let query = sqlx::query(
             "INSERT INTO liquidation_history (
                     id, position_id, position_owner, liquidator, symbol,
                     liquidated_size, liquidation_price, margin_before, margin_after,
-                    liquidator_reward, bad_debt, created_at
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"
+                    liquidator_reward, insurance_contribution, slippage, bad_debt, unabsorbed,
+                    realized_pnl, unrealized_pnl, liquidation_fee, fee_tier, kind, created_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)"
             )
             .bind(rec.id)
             .bind(rec.position_id)
@@ -148,21 +641,35 @@ impl LiquidationExecutor {
             .bind(rec.margin_before)
             .bind(rec.margin_after)
             .bind(rec.liquidator_reward)
+            .bind(rec.insurance_contribution)
+            .bind(rec.slippage)
             .bind(rec.bad_debt)
-            .bind(rec.timestamp)
-            .execute(&self.db)
-            .await?;
-        Ok(())
+            .bind(rec.unabsorbed)
+            .bind(rec.realized_pnl)
+            .bind(rec.unrealized_pnl)
+            .bind(rec.liquidation_fee)
+            .bind(&rec.fee_tier)
+            .bind(rec.kind.as_str())
+            .bind(rec.timestamp);
+        self.db.execute(query).await
     }
-}
 
-fn maintenance_margin(leverage: u16) -> f64 {
-    match leverage {
-        1..=20 => 0.025,
-        21..=50 => 0.01,
-        51..=100 => 0.005,
-        101..=500 => 0.0025,
-        501..=1000 => 0.001,
-        _ => 0.025,
+    async fn insert_socialized_loss(&self, rec: &SocializedLossRecord) -> Result<(), sqlx::Error> {
+        let query = sqlx::query(
+            "INSERT INTO socialized_loss_history (
+                    id, source_position_id, position_id, position_owner, symbol,
+                    haircut, margin_before, margin_after, created_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+            )
+            .bind(rec.id)
+            .bind(rec.source_position_id)
+            .bind(rec.position_id)
+            .bind(&rec.position_owner)
+            .bind(&rec.symbol)
+            .bind(rec.haircut)
+            .bind(rec.margin_before)
+            .bind(rec.margin_after)
+            .bind(rec.timestamp);
+        self.db.execute(query).await
     }
 }
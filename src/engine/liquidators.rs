@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+/// A third-party liquidator and the rewards credited to it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiquidatorAccount {
+    pub name: String,
+    pub balance: i64,
+    pub total_rewards: i64,
+    pub takeovers: u64,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// Registered liquidators, looked up by API key.
+#[derive(Default)]
+pub struct LiquidatorRegistry {
+    accounts: HashMap<String, LiquidatorAccount>,
+    keys: HashMap<String, String>,
+}
+
+impl LiquidatorRegistry {
+    /// Registers `name` at `now` and returns its API key, or `None` if the
+    /// name is taken.
+    pub fn register(&mut self, name: &str, now: DateTime<Utc>) -> Option<String> {
+        if self.accounts.contains_key(name) { return None; }
+        let key = Uuid::new_v4().simple().to_string();
+        self.accounts.insert(name.to_string(), LiquidatorAccount {
+            name: name.to_string(),
+            balance: 0,
+            total_rewards: 0,
+            takeovers: 0,
+            registered_at: now,
+        });
+        self.keys.insert(key.clone(), name.to_string());
+        Some(key)
+    }
+
+    /// Name of the liquidator holding `api_key`.
+    pub fn authenticate(&self, api_key: &str) -> Option<&str> {
+        self.keys.get(api_key).map(String::as_str)
+    }
+
+    pub fn account(&self, name: &str) -> Option<&LiquidatorAccount> {
+        self.accounts.get(name)
+    }
+
+    /// Credits a takeover reward; returns the updated account.
+    pub fn credit(&mut self, name: &str, reward: i64) -> Option<LiquidatorAccount> {
+        let acct = self.accounts.get_mut(name)?;
+        acct.balance = acct.balance.saturating_add(reward);
+        acct.total_rewards = acct.total_rewards.saturating_add(reward);
+        acct.takeovers += 1;
+        Some(acct.clone())
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use log::error;
+use uuid::Uuid;
+use crate::engine::config::MarginCallConfig;
+use crate::engine::models::MarginCallEvent;
+
+/// How many warning thresholds each position is below, so a user hears
+/// about each one once until the ratio has recovered past its hysteresis band.
+#[derive(Default)]
+pub struct MarginCallTracker {
+    levels: HashMap<Uuid, usize>,
+}
+
+impl MarginCallTracker {
+    /// Updates `id`'s warning level for `ratio` and returns the threshold
+    /// (as a multiple of maintenance) it has just fallen below, if any.
+    pub fn observe(&mut self, cfg: &MarginCallConfig, id: Uuid, ratio: f64, maintenance: f64) -> Option<f64> {
+        let mut thresholds = cfg.thresholds.clone();
+        thresholds.sort_by(|a, b| b.total_cmp(a));
+
+        let below = |band: f64| thresholds.iter().filter(|t| ratio < *t * maintenance * band).count();
+        let breached = below(1.0);
+        let level = self.levels.entry(id).or_default();
+
+        if breached > *level {
+            *level = breached;
+            return Some(thresholds[breached - 1]);
+        }
+        // only re-arm once the ratio is clear of the hysteresis band
+        *level = (*level).min(below(1.0 + cfg.hysteresis_bps as f64 / 10_000.0));
+        None
+    }
+
+    pub fn forget(&mut self, id: Uuid) {
+        self.levels.remove(&id);
+    }
+}
+
+/// POSTs `event` as JSON to each of `urls`.
+pub async fn notify_webhooks(urls: &[String], event: &MarginCallEvent) {
+    let Ok(body) = serde_json::to_string(event) else { return };
+    let client = hyper::Client::new();
+
+    for url in urls {
+        let req = hyper::Request::post(url.as_str())
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(body.clone()));
+        let res = match req {
+            Ok(req) => client.request(req).await.map(|r| r.status()).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match res {
+            Ok(status) if status.is_success() => {}
+            Ok(status) => error!("Margin call webhook {} returned {}", url, status),
+            Err(e) => error!("Margin call webhook {} failed: {}", url, e),
+        }
+    }
+}
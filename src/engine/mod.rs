@@ -1,59 +1,139 @@
+pub mod config;
 pub mod models;
 pub mod oracle;
 pub mod position_monitor;
 pub mod liquidation_executor;
+pub mod socialized_loss;
+pub mod adl;
+pub mod insurance;
+pub mod risk;
+pub mod lifecycle;
+pub mod execution;
+pub mod auction;
+pub mod liquidators;
+pub mod owners;
+pub mod backstop;
+pub mod waterfall;
+pub mod margin_call;
+pub mod accounts;
+pub mod portfolio;
+pub mod funding;
+pub mod fees;
+pub mod orders;
+pub mod throttle;
+pub mod controls;
+pub mod shadow;
+pub mod store;
+pub mod backtest;
+pub mod clock;
+pub mod scenario;
 
 #[cfg(test)]
 mod test;
 
-use sqlx::PgPool;
 use std::sync::Arc;
 use tokio::sync::{broadcast, Mutex};
-use crate::engine::models::{InsuranceFund, Position};
+use crate::engine::accounts::Accounts;
+use crate::engine::auction::Auction;
+use crate::engine::backstop::BackstopPool;
+use crate::engine::clock::Clock;
+use crate::engine::config::EngineConfig;
+use crate::engine::controls::Controls;
+use crate::engine::execution::{venue_from_config, ExecutionVenue};
+use crate::engine::fees::FeeAccount;
+use crate::engine::funding::{FundingBook, FundingEngine};
+use crate::engine::insurance::InsuranceFunds;
+use crate::engine::liquidators::LiquidatorRegistry;
+use crate::engine::owners::OwnerKeys;
+use crate::engine::models::{EngineEvent, LiquidationRecord, Position};
 use crate::engine::oracle::PriceOracle;
+use crate::engine::throttle::Throttle;
+use crate::engine::shadow::ShadowBook;
+use crate::engine::store::Store;
+use crate::engine::waterfall::Waterfall;
 use crate::engine::position_monitor::PositionMonitor;
-use crate::engine::liquidation_executor::LiquidationExecutor;
+use crate::engine::liquidation_executor::{LiquidationExecutor, TakeoverError};
+use uuid::Uuid;
 
 pub struct EngineState {
-    pub db: PgPool,
+    pub db: Store,
+    pub config: EngineConfig,
     pub oracle: Arc<PriceOracle>,
     pub positions: Arc<Mutex<Vec<Position>>>,
-    pub insurance: Arc<Mutex<InsuranceFund>>,
-    pub event_tx: Arc<broadcast::Sender<crate::engine::models::LiquidationEvent>>,
+    pub insurance: Arc<Mutex<InsuranceFunds>>,
+    pub venue: Arc<dyn ExecutionVenue>,
+    /// The one waterfall every liquidation, pass or takeover, goes through.
+    pub waterfall: Arc<Waterfall>,
+    pub auctions: Arc<Mutex<Vec<Auction>>>,
+    pub liquidators: Arc<Mutex<LiquidatorRegistry>>,
+    pub owner_keys: Arc<Mutex<OwnerKeys>>,
+    pub backstop: Arc<Mutex<BackstopPool>>,
+    pub accounts: Arc<Mutex<Accounts>>,
+    pub funding: Arc<Mutex<FundingBook>>,
+    pub fees: Arc<Mutex<FeeAccount>>,
+    pub throttle: Arc<Mutex<Throttle>>,
+    pub controls: Arc<Mutex<Controls>>,
+    pub shadow: Arc<Mutex<ShadowBook>>,
+    pub event_tx: Arc<broadcast::Sender<EngineEvent>>,
+    pub clock: Arc<dyn Clock>,
 }
 
 impl EngineState {
     pub async fn new(
-        db: PgPool,
-        event_tx: Arc<broadcast::Sender<crate::engine::models::LiquidationEvent>>,
+        db: Store,
+        event_tx: Arc<broadcast::Sender<EngineEvent>>,
+        config: EngineConfig,
+        clock: Arc<dyn Clock>,
     ) -> anyhow::Result<Self> {
         let positions = Position::seed_defaults();
 
-        let insurance = InsuranceFund {
-            balance: 1_000_000,
-            total_contributions: 1_000_000,
-            total_bad_debt_covered: 0,
-        };
+        let insurance = InsuranceFunds::from_config(&config.insurance);
+        let accounts = Accounts::from_config(&config);
+        let venue = Arc::from(venue_from_config(&config));
+        let waterfall = Arc::new(Waterfall::from_config(&config.waterfall));
 
         Ok(Self {
             db,
+            config,
             oracle: Arc::new(PriceOracle::new()),
             positions: Arc::new(Mutex::new(positions)),
             insurance: Arc::new(Mutex::new(insurance)),
+            venue,
+            waterfall,
+            auctions: Arc::new(Mutex::new(Vec::new())),
+            liquidators: Arc::new(Mutex::new(LiquidatorRegistry::default())),
+            owner_keys: Arc::new(Mutex::new(OwnerKeys::default())),
+            backstop: Arc::new(Mutex::new(BackstopPool::default())),
+            accounts: Arc::new(Mutex::new(accounts)),
+            funding: Arc::new(Mutex::new(FundingBook::default())),
+            fees: Arc::new(Mutex::new(FeeAccount::default())),
+            throttle: Arc::new(Mutex::new(Throttle::default())),
+            controls: Arc::new(Mutex::new(Controls::default())),
+            shadow: Arc::new(Mutex::new(ShadowBook::default())),
             event_tx,
+            clock,
         })
     }
 
+    /// Has `liquidator` take over `size` of a liquidatable position, through
+    /// the same waterfall, throttle and statuses as the executor's passes.
+    pub async fn takeover(self: &Arc<Self>, liquidator: &str, position_id: Uuid, size: i64) -> Result<LiquidationRecord, TakeoverError> {
+        LiquidationExecutor::new(self.clone()).takeover(liquidator, position_id, size).await
+    }
+
     pub async fn start(self: Arc<Self>) {
         // clone values we'll move into join
         let oracle = self.oracle.clone();
+        let clock = self.clock.clone();
         let monitor = PositionMonitor::new(self.clone());
         let executor = LiquidationExecutor::new(self.clone());
+        let funding = FundingEngine::new(self.clone());
 
         tokio::join!(
-            async move { oracle.start().await },
+            async move { oracle.start(clock).await },
             async move { monitor.run().await },
-            async move { executor.run().await }
+            async move { executor.run().await },
+            async move { funding.run().await }
         );
     }
 }
@@ -1,6 +1,8 @@
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::engine::auction::AuctionEvent;
+use crate::engine::controls::Controls;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Position {
@@ -13,6 +15,72 @@ pub struct Position {
     pub is_long: bool,
     pub leverage: u16,
     pub open: bool,
+    /// PnL realized by reductions so far; already settled into `margin`.
+    #[serde(default)]
+    pub realized_pnl: i64,
+    /// Socialized loss haircuts taken from this position's profit so far.
+    #[serde(default)]
+    pub haircuts: i64,
+    #[serde(default)]
+    pub margin_mode: MarginMode,
+    #[serde(default)]
+    pub liquidation: LiquidationStatus,
+}
+
+/// Whether a position is backed by its own `margin` or by its owner's
+/// account collateral, shared with the owner's other cross positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarginMode {
+    #[default]
+    Isolated,
+    Cross,
+}
+
+impl MarginMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarginMode::Isolated => "isolated",
+            MarginMode::Cross => "cross",
+        }
+    }
+}
+
+/// Where a position is in the liquidation lifecycle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidationStage {
+    #[default]
+    Healthy,
+    /// Below the market's target ratio but not yet liquidatable.
+    MarginCall,
+    PartiallyLiquidated,
+    /// Just had a partial step; no further steps until `cooldown_until`.
+    CoolingDown,
+    FullyLiquidated,
+}
+
+impl LiquidationStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LiquidationStage::Healthy => "healthy",
+            LiquidationStage::MarginCall => "margin_call",
+            LiquidationStage::PartiallyLiquidated => "partially_liquidated",
+            LiquidationStage::CoolingDown => "cooling_down",
+            LiquidationStage::FullyLiquidated => "fully_liquidated",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LiquidationStatus {
+    pub stage: LiquidationStage,
+    /// Partial steps taken since `window_started_at`.
+    pub steps_in_window: u32,
+    pub window_started_at: Option<DateTime<Utc>>,
+    pub cooldown_until: Option<DateTime<Utc>>,
+    /// When the position last dropped below maintenance.
+    pub liquidatable_since: Option<DateTime<Utc>>,
 }
 
 impl Position {
@@ -28,20 +96,71 @@ impl Position {
                 is_long: true,
                 leverage: 100,
                 open: true,
+                realized_pnl: 0,
+                haircuts: 0,
+                margin_mode: Default::default(),
+                liquidation: Default::default(),
             },
             Position {
                 id: Uuid::new_v4(),
                 owner: "bob".into(),
                 symbol: "ETH-USD".into(),
                 size: 200,
-                entry_price: 3_000_000_00i64, // 3,000 * 1e6 (note: consistent scaling)
+                entry_price: 300_000_000i64, // 3,000 * 1e6 (note: consistent scaling)
                 margin: 3_000,
                 is_long: false,
                 leverage: 50,
                 open: true,
+                realized_pnl: 0,
+                haircuts: 0,
+                margin_mode: Default::default(),
+                liquidation: Default::default(),
             },
         ]
     }
+
+    /// Unrealized PnL at `mark` (size * price delta, both scaled).
+    pub fn unrealized_pnl(&self, mark: i64) -> i128 {
+        self.pnl(self.size, mark)
+    }
+
+    /// PnL of `size` units of the position at `price`.
+    pub fn pnl(&self, size: i64, price: i64) -> i128 {
+        if self.is_long {
+            (size as i128) * (price as i128 - self.entry_price as i128)
+        } else {
+            (size as i128) * (self.entry_price as i128 - price as i128)
+        }
+    }
+
+    /// `size` of this position as a new isolated one `owner` holds from
+    /// `price`, with no margin of its own: how the backstop pool and the
+    /// insurance fund take positions over.
+    pub fn transferred(&self, owner: &str, size: i64, price: i64) -> Position {
+        Position {
+            id: Uuid::new_v4(),
+            owner: owner.to_string(),
+            size,
+            entry_price: price,
+            margin: 0,
+            open: true,
+            realized_pnl: 0,
+            haircuts: 0,
+            margin_mode: Default::default(),
+            liquidation: Default::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Closes `size` units at `price`, settling their PnL into margin.
+    /// Returns the PnL realized.
+    pub fn realize(&mut self, size: i64, price: i64) -> i64 {
+        let realized = self.pnl(size, price) as i64;
+        self.size -= size;
+        self.margin = self.margin.saturating_add(realized);
+        self.realized_pnl = self.realized_pnl.saturating_add(realized);
+        realized
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -49,6 +168,32 @@ pub struct InsuranceFund {
     pub balance: i64,
     pub total_contributions: i64,
     pub total_bad_debt_covered: i64,
+    /// Isolated sub-funds only: what the market has drawn from the shared
+    /// fund so far, counted against its `max_shared_draw`.
+    #[serde(default)]
+    pub shared_drawn: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidationKind {
+    Partial,
+    Full,
+    /// Counterparty closed by auto-deleveraging.
+    Adl,
+    /// Taken over by a liquidator in a Dutch auction.
+    Auction,
+}
+
+impl LiquidationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LiquidationKind::Partial => "partial",
+            LiquidationKind::Full => "full",
+            LiquidationKind::Adl => "adl",
+            LiquidationKind::Auction => "auction",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -63,7 +208,24 @@ pub struct LiquidationRecord {
     pub margin_before: i64,
     pub margin_after: i64,
     pub liquidator_reward: i64,
+    pub insurance_contribution: i64,
+    /// Cost of filling away from mark.
+    pub slippage: i64,
+    /// Deficit the insurance fund covered.
     pub bad_debt: i64,
+    /// Deficit left over once the fund and socialized loss had done what
+    /// they could.
+    #[serde(default)]
+    pub unabsorbed: i64,
+    /// PnL of the closed size at the liquidation price.
+    pub realized_pnl: i64,
+    /// PnL at mark of what's left of the position.
+    pub unrealized_pnl: i64,
+    /// Fee charged to the liquidated position, on top of the penalty.
+    pub liquidation_fee: i64,
+    /// Fee tier the liquidation fee was charged at.
+    pub fee_tier: String,
+    pub kind: LiquidationKind,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -71,3 +233,183 @@ pub struct LiquidationRecord {
 pub struct LiquidationEvent {
     pub record: LiquidationRecord,
 }
+
+/// A haircut taken from a profitable position to absorb bad debt the
+/// insurance fund could not cover.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SocializedLossRecord {
+    pub id: Uuid,
+    pub source_position_id: Uuid,
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    pub haircut: i64,
+    pub margin_before: i64,
+    pub margin_after: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SocializedLossEvent {
+    pub record: SocializedLossRecord,
+}
+
+/// Non-cash collateral sold to bring an account back above maintenance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollateralSale {
+    pub id: Uuid,
+    pub owner: String,
+    pub asset: String,
+    pub quantity: i64,
+    pub price: i64,
+    pub proceeds: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollateralSaleEvent {
+    pub sale: CollateralSale,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liquidity {
+    Maker,
+    #[default]
+    Taker,
+}
+
+impl Liquidity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Liquidity::Maker => "maker",
+            Liquidity::Taker => "taker",
+        }
+    }
+}
+
+/// A fill that opened or added to a position, and the fee charged on it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trade {
+    pub id: Uuid,
+    pub position_id: Uuid,
+    pub owner: String,
+    pub symbol: String,
+    pub is_long: bool,
+    pub size: i64,
+    pub price: i64,
+    pub liquidity: Liquidity,
+    pub fee: i64,
+    pub fee_tier: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TradeEvent {
+    pub trade: Trade,
+}
+
+/// A symbol's funding rate for one interval, from the average premium of
+/// its mark over its index.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FundingRate {
+    pub symbol: String,
+    /// Average (mark - index) / index over the interval.
+    pub premium: f64,
+    /// Fraction of notional longs pay shorts (negative: shorts pay longs).
+    pub rate: f64,
+    pub mark: i64,
+    pub index: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Funding paid (negative) or received by one position.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FundingPayment {
+    pub id: Uuid,
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    pub rate: f64,
+    pub amount: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FundingEvent {
+    pub rates: Vec<FundingRate>,
+    pub payments: Vec<FundingPayment>,
+}
+
+/// A step the executor would have taken in shadow mode. Nothing was closed:
+/// sizes are of the position as it stood.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WouldLiquidate {
+    pub id: Uuid,
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    pub is_long: bool,
+    pub margin_mode: MarginMode,
+    pub position_size: i64,
+    pub size: i64,
+    pub mark: i64,
+    /// `size` at mark.
+    pub notional: i64,
+    pub margin_ratio: f64,
+    pub maintenance_margin: f64,
+    pub target_ratio: f64,
+    /// Below zero equity: the whole position would be closed out.
+    pub bankrupt: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A position just became liquidatable; registered liquidators may take it over.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiquidatableEvent {
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    pub is_long: bool,
+    pub size: i64,
+    pub mark: i64,
+    pub margin_ratio: f64,
+    /// Largest size a single takeover may close.
+    pub max_takeover_size: i64,
+}
+
+/// A position's margin ratio fell below one of the warning thresholds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MarginCallEvent {
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    pub mark: i64,
+    pub margin_ratio: f64,
+    pub maintenance: f64,
+    /// The threshold crossed, as a multiple of maintenance.
+    pub threshold: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// An operator changed the engine's controls.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ControlsEvent {
+    pub controls: Controls,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Everything pushed to WebSocket subscribers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEvent {
+    Liquidation(LiquidationEvent),
+    SocializedLoss(SocializedLossEvent),
+    Auction(AuctionEvent),
+    Liquidatable(LiquidatableEvent),
+    MarginCall(MarginCallEvent),
+    CollateralSale(CollateralSaleEvent),
+    Funding(FundingEvent),
+    Trade(TradeEvent),
+    Controls(ControlsEvent),
+}
@@ -1,12 +1,21 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::time::{sleep, Duration};
+use std::time::Duration;
 use log::info;
+use crate::engine::clock::Clock;
 
 #[derive(Clone)]
 pub struct PriceOracle {
     prices: Arc<RwLock<HashMap<String, i64>>>, // scaled price (1e6)
+    /// Spot index the perps' funding is pegged to; the marks trail it.
+    index: Arc<RwLock<HashMap<String, i64>>>,
+}
+
+impl Default for PriceOracle {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PriceOracle {
@@ -14,7 +23,7 @@ impl PriceOracle {
         let mut m = HashMap::new();
         m.insert("BTC-USD".to_string(), 50_000_000_000i64); // 60,000 * 1e6
         m.insert("ETH-USD".to_string(), 2_800_000_000i64);  // 2,800 * 1e6
-        Self { prices: Arc::new(RwLock::new(m)) }
+        Self { index: Arc::new(RwLock::new(m.clone())), prices: Arc::new(RwLock::new(m)) }
     }
 
     pub async fn get_mark_price(&self, symbol: &str) -> Option<i64> {
@@ -22,17 +31,43 @@ impl PriceOracle {
         map.get(symbol).copied()
     }
 
-    pub async fn start(self: Arc<Self>) {
+    /// Every mark price at once.
+    pub async fn snapshot(&self) -> HashMap<String, i64> {
+        self.prices.read().await.clone()
+    }
+
+    /// Every index price at once.
+    pub async fn index_snapshot(&self) -> HashMap<String, i64> {
+        self.index.read().await.clone()
+    }
+
+    /// Sets `symbol`'s mark, and its index (the mark itself if not given).
+    pub async fn set(&self, symbol: &str, mark: i64, index: Option<i64>) {
+        self.prices.write().await.insert(symbol.to_string(), mark);
+        self.index.write().await.insert(symbol.to_string(), index.unwrap_or(mark));
+    }
+
+    /// Forgets every price, leaving only what is `set` afterwards.
+    pub async fn clear(&self) {
+        self.prices.write().await.clear();
+        self.index.write().await.clear();
+    }
+
+    pub async fn start(self: Arc<Self>, clock: Arc<dyn Clock>) {
         loop {
-            sleep(Duration::from_millis(1500)).await;
+            clock.sleep(Duration::from_millis(1500)).await;
             let mut w = self.prices.write().await;
+            let mut idx = self.index.write().await;
             if let Some(btc) = w.get_mut("BTC-USD") {
 
                 *btc = (*btc).saturating_sub(500_000);
+                // the index leads the mark down by one tick
+                idx.insert("BTC-USD".to_string(), (*btc).saturating_sub(500_000));
                 info!("Oracle: BTC-USD price updated to {}", *btc);
             }
             if let Some(eth) = w.get_mut("ETH-USD") {
                 *eth = (*eth).saturating_sub(20_000);
+                idx.insert("ETH-USD".to_string(), (*eth).saturating_sub(20_000));
                 info!("Oracle: ETH-USD price updated to {}", *eth);
             }
         }
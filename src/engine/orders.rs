@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::engine::accounts::Accounts;
+use crate::engine::config::EngineConfig;
+use crate::engine::{fees, risk};
+use crate::engine::models::{EngineEvent, Liquidity, MarginMode, Position, Trade, TradeEvent};
+use crate::engine::EngineState;
+
+/// A market order opening a position, or adding to an open one on the
+/// same side, at mark.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OrderRequest {
+    pub owner: String,
+    pub symbol: String,
+    pub is_long: bool,
+    pub size: i64,
+    /// Margin posted with an isolated order. Cross orders draw on the account.
+    #[serde(default)]
+    pub margin: i64,
+    /// Cross only: leverage the position is margined at (default: the
+    /// market's max). Isolated positions take theirs from margin and notional.
+    #[serde(default)]
+    pub leverage: Option<u16>,
+    #[serde(default)]
+    pub margin_mode: MarginMode,
+    #[serde(default)]
+    pub liquidity: Liquidity,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OrderError {
+    InvalidSize,
+    UnknownSymbol,
+    /// The posted margin (or the account, if cross) doesn't cover the
+    /// fee and initial margin.
+    InsufficientMargin,
+    ExceedsMaxLeverage,
+    /// Operators have put the symbol, or the engine, in reduce-only mode.
+    ReduceOnly,
+    /// The engine is running in shadow mode and changes no positions.
+    Shadow,
+}
+
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::InvalidSize => write!(f, "size and margin must be positive"),
+            OrderError::UnknownSymbol => write!(f, "no mark price for symbol"),
+            OrderError::InsufficientMargin => write!(f, "margin does not cover the fee and initial margin"),
+            OrderError::ExceedsMaxLeverage => write!(f, "position would exceed the market's max leverage"),
+            OrderError::ReduceOnly => write!(f, "reduce-only: orders adding risk are not accepted"),
+            OrderError::Shadow => write!(f, "engine is in shadow mode"),
+        }
+    }
+}
+
+/// Fills `req` at mark and charges its maker or taker fee: out of the
+/// posted margin if isolated, out of the account's collateral if cross.
+/// An isolated position's leverage is its notional over its equity after
+/// the fill, rounded up; a cross account must hold the initial margin of
+/// all its cross positions.
+pub fn fill(
+    cfg: &EngineConfig,
+    positions: &mut Vec<Position>,
+    accounts: &mut Accounts,
+    req: &OrderRequest,
+    marks: &HashMap<String, i64>,
+    now: DateTime<Utc>,
+) -> Result<Trade, OrderError> {
+    let cross = req.margin_mode == MarginMode::Cross;
+    if req.size <= 0 || req.margin < 0 || (!cross && req.margin == 0) {
+        return Err(OrderError::InvalidSize);
+    }
+    let &mark = marks.get(&req.symbol).ok_or(OrderError::UnknownSymbol)?;
+    let max_leverage = cfg.market(&req.symbol).max_leverage;
+
+    let (tier, rates) = cfg.fees.tier(&req.owner);
+    let bps = match req.liquidity {
+        Liquidity::Maker => rates.maker_bps,
+        Liquidity::Taker => rates.taker_bps,
+    };
+    let fee = fees::fee(req.size as i128 * mark as i128, bps);
+    if !cross && req.margin <= fee { return Err(OrderError::InsufficientMargin); }
+
+    let existing = positions.iter().position(|p| {
+        p.open && p.owner == req.owner && p.symbol == req.symbol && p.is_long == req.is_long && p.margin_mode == req.margin_mode
+    });
+    let mut pos = match existing {
+        Some(i) => {
+            let mut pos = positions[i].clone();
+            let size = pos.size + req.size;
+            pos.entry_price = ((pos.size as i128 * pos.entry_price as i128 + req.size as i128 * mark as i128) / size as i128) as i64;
+            pos.size = size;
+            pos
+        }
+        None => Position {
+            id: Uuid::new_v4(),
+            owner: req.owner.clone(),
+            symbol: req.symbol.clone(),
+            size: req.size,
+            entry_price: mark,
+            margin: 0,
+            is_long: req.is_long,
+            leverage: 1,
+            open: true,
+            realized_pnl: 0,
+            haircuts: 0,
+            margin_mode: req.margin_mode,
+            liquidation: Default::default(),
+        },
+    };
+
+    if cross {
+        let leverage = req.leverage.unwrap_or(max_leverage);
+        if leverage == 0 || leverage > max_leverage { return Err(OrderError::ExceedsMaxLeverage); }
+        pos.leverage = leverage;
+
+        let mut after = positions.clone();
+        match existing {
+            Some(i) => after[i] = pos.clone(),
+            None => after.push(pos.clone()),
+        }
+        let equity = accounts.equity(&req.owner, &after, marks) - fee;
+        if equity < accounts.initial_requirement(&req.owner, &after, marks) {
+            return Err(OrderError::InsufficientMargin);
+        }
+        accounts.credit(&req.owner, -fee);
+    } else {
+        pos.margin = pos.margin.saturating_add(req.margin - fee);
+        let leverage = risk::effective_leverage(&pos, mark).ok_or(OrderError::InsufficientMargin)?.ceil();
+        if leverage > max_leverage as f64 { return Err(OrderError::ExceedsMaxLeverage); }
+        pos.leverage = (leverage as u16).max(1);
+    }
+
+    let position_id = pos.id;
+    match existing {
+        Some(i) => positions[i] = pos,
+        None => positions.push(pos),
+    }
+
+    Ok(Trade {
+        id: Uuid::new_v4(),
+        position_id,
+        owner: req.owner.clone(),
+        symbol: req.symbol.clone(),
+        is_long: req.is_long,
+        size: req.size,
+        price: mark,
+        liquidity: req.liquidity,
+        fee,
+        fee_tier: tier.to_string(),
+        timestamp: now,
+    })
+}
+
+/// Fills `req` against the engine's state, routes its fee, and records
+/// and broadcasts the trade.
+pub async fn place(state: &EngineState, req: &OrderRequest) -> Result<Trade, OrderError> {
+    if state.config.shadow.enabled {
+        return Err(OrderError::Shadow);
+    }
+    // every order opens or adds to a position
+    if !state.controls.lock().await.accepts_orders(&req.symbol) {
+        return Err(OrderError::ReduceOnly);
+    }
+    let marks = state.oracle.snapshot().await;
+    let trade = {
+        let mut positions = state.positions.lock().await;
+        let mut accounts = state.accounts.lock().await;
+        fill(&state.config, &mut positions, &mut accounts, req, &marks, state.clock.now())?
+    };
+    {
+        let mut insurance = state.insurance.lock().await;
+        let mut account = state.fees.lock().await;
+        fees::route_trading(&state.config.fees, &mut account, &mut insurance, &trade.symbol, trade.fee);
+    }
+    info!("{} {} {} {} at {} (fee {}, {})",
+        trade.owner, if trade.is_long { "bought" } else { "sold" }, trade.size, trade.symbol, trade.price, trade.fee, trade.liquidity.as_str());
+
+    let query = sqlx::query(
+        "INSERT INTO trades (id, position_id, owner, symbol, is_long, size, price, liquidity, fee, fee_tier, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+        )
+        .bind(trade.id)
+        .bind(trade.position_id)
+        .bind(&trade.owner)
+        .bind(&trade.symbol)
+        .bind(trade.is_long)
+        .bind(trade.size)
+        .bind(trade.price)
+        .bind(trade.liquidity.as_str())
+        .bind(trade.fee)
+        .bind(&trade.fee_tier)
+        .bind(trade.timestamp);
+    let res = state.db.execute(query).await;
+    if let Err(e) = res {
+        error!("DB insert failed (trade): {:?}", e);
+    }
+    let _ = state.event_tx.send(EngineEvent::Trade(TradeEvent { trade: trade.clone() }));
+    Ok(trade)
+}
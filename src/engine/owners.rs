@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// API keys account owners trade and move collateral with, kept apart from
+/// the liquidator registry so a liquidator key never acts for an account.
+#[derive(Default)]
+pub struct OwnerKeys {
+    /// Key -> owner.
+    keys: HashMap<String, String>,
+    /// Owner -> key.
+    owners: HashMap<String, String>,
+}
+
+impl OwnerKeys {
+    /// Issues `owner`'s API key, or `None` if it already has one.
+    pub fn issue(&mut self, owner: &str) -> Option<String> {
+        if self.owners.contains_key(owner) { return None; }
+        Some(self.rotate(owner))
+    }
+
+    /// Replaces `owner`'s key (issuing one if it has none) and returns it.
+    pub fn rotate(&mut self, owner: &str) -> String {
+        let key = Uuid::new_v4().simple().to_string();
+        if let Some(old) = self.owners.insert(owner.to_string(), key.clone()) {
+            self.keys.remove(&old);
+        }
+        self.keys.insert(key.clone(), owner.to_string());
+        key
+    }
+
+    /// Owner holding `api_key`.
+    pub fn authenticate(&self, api_key: &str) -> Option<&str> {
+        self.keys.get(api_key).map(String::as_str)
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use crate::engine::config::PortfolioMarginConfig;
+use crate::engine::models::Position;
+
+/// Requirement for `positions` as a portfolio: the worst loss over every
+/// combination of one shock per group, floored at `floor_bps` of gross
+/// notional. Offsetting positions in a group net out in each scenario.
+pub fn requirement<'a>(
+    cfg: &PortfolioMarginConfig,
+    positions: impl IntoIterator<Item = &'a Position>,
+    marks: &HashMap<String, i64>,
+) -> i64 {
+    // signed notional per group
+    let mut exposure: Vec<i128> = vec![0; cfg.groups.len()];
+    let mut own: HashMap<&str, usize> = HashMap::new();
+    let mut gross: i128 = 0;
+    for pos in positions {
+        let Some(&mark) = marks.get(&pos.symbol) else { continue };
+        let notional = pos.size as i128 * mark as i128;
+        gross += notional;
+
+        let group = cfg.groups.iter().position(|g| g.contains(&pos.symbol)).unwrap_or_else(|| {
+            *own.entry(pos.symbol.as_str()).or_insert_with(|| {
+                exposure.push(0);
+                exposure.len() - 1
+            })
+        });
+        exposure[group] += if pos.is_long { notional } else { -notional };
+    }
+
+    // groups are independent, so the worst scenario is each group's worst shock
+    let worst: i128 = exposure
+        .iter()
+        .map(|net| {
+            cfg.shocks_bps
+                .iter()
+                .map(|shock| -net * *shock as i128 / 10_000)
+                .max()
+                .unwrap_or(0)
+                .max(0)
+        })
+        .sum();
+    let floor = gross * cfg.floor_bps as i128 / 10_000;
+    worst.max(floor) as i64
+}
@@ -1,7 +1,9 @@
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
-use log::info;
-use crate::engine::EngineState;
+use std::time::Duration;
+use log::{info, warn};
+use crate::engine::{EngineState, margin_call, risk};
+use crate::engine::margin_call::MarginCallTracker;
+use crate::engine::models::{EngineEvent, MarginCallEvent};
 
 pub struct PositionMonitor {
     state: Arc<EngineState>,
@@ -11,44 +13,57 @@ impl PositionMonitor {
     pub fn new(state: Arc<EngineState>) -> Self { Self { state } }
 
     pub async fn run(self) {
+        let mut margin_calls = MarginCallTracker::default();
+
         loop {
-            sleep(Duration::from_millis(1000)).await;
-
-            let positions = { self.state.positions.lock().await.clone() };
-            for pos in positions {
-                if !pos.open { continue; }
-                if let Some(mark) = self.state.oracle.get_mark_price(&pos.symbol).await {
-                    // unrealized pnl = size * (mark - entry)  (note: both scaled)
-                    let unrealized = if pos.is_long {
-                        (pos.size as i128) * (mark as i128 - pos.entry_price as i128)
-                    } else {
-                        (pos.size as i128) * (pos.entry_price as i128 - mark as i128)
-                    };
+            self.state.clock.sleep(Duration::from_millis(1000)).await;
+            self.check(&mut margin_calls).await;
+        }
+    }
 
-                    let pos_value = (pos.size as i128) * (mark as i128);
-                    if pos_value <= 0 { continue; }
+    /// Checks every open position once.
+    pub async fn check(&self, margin_calls: &mut MarginCallTracker) {
+        let cfg = &self.state.config.margin_call;
+        let now = self.state.clock.now();
+        if !self.state.controls.lock().await.monitors() { return; }
 
-                    // margin ratio as float
-                    let margin_ratio = (pos.margin as i128 + unrealized) as f64 / pos_value as f64;
-                    let maintenance = maintenance_margin_ratio(pos.leverage);
+        let positions = { self.state.positions.lock().await.clone() };
+        let marks = self.state.oracle.snapshot().await;
+        for pos in &positions {
+            if !pos.open {
+                margin_calls.forget(pos.id);
+                continue;
+            }
+            if let Some(&mark) = marks.get(&pos.symbol) {
+                // margin ratio as float; cross positions are judged on their account
+                let view = self.state.accounts.lock().await.view(pos, &positions, &marks);
+                let Some(margin_ratio) = risk::margin_ratio(&view, mark) else { continue };
+                let maintenance = risk::maintenance_margin(pos.leverage);
 
-                    if margin_ratio < maintenance {
-                        info!("Position {} liquidatable (ratio {:.6} < {:.6})", pos.id, margin_ratio, maintenance);
-                        // we simply log here; executor will pick up and act
+                if margin_ratio < maintenance {
+                    info!("Position {} liquidatable (ratio {:.6} < {:.6})", pos.id, margin_ratio, maintenance);
+                    // we simply log here; executor will pick up and act
+                }
+
+                if let Some(threshold) = margin_calls.observe(cfg, pos.id, margin_ratio, maintenance) {
+                    warn!("Margin call for pos {} (ratio {:.6} < {}x maintenance)", pos.id, margin_ratio, threshold);
+                    let event = MarginCallEvent {
+                        position_id: pos.id,
+                        position_owner: pos.owner.clone(),
+                        symbol: pos.symbol.clone(),
+                        mark,
+                        margin_ratio,
+                        maintenance,
+                        threshold,
+                        timestamp: now,
+                    };
+                    if !cfg.webhooks.is_empty() {
+                        let (urls, event) = (cfg.webhooks.clone(), event.clone());
+                        tokio::spawn(async move { margin_call::notify_webhooks(&urls, &event).await });
                     }
+                    let _ = self.state.event_tx.send(EngineEvent::MarginCall(event));
                 }
             }
         }
     }
 }
-
-fn maintenance_margin_ratio(leverage: u16) -> f64 {
-    match leverage {
-        1..=20 => 0.025,
-        21..=50 => 0.01,
-        51..=100 => 0.005,
-        101..=500 => 0.0025,
-        501..=1000 => 0.001,
-        _ => 0.025,
-    }
-}
@@ -0,0 +1,74 @@
+use crate::engine::models::Position;
+
+/// Most leverage any position or market may carry. Config and snapshots
+/// above it are refused, so the tiers never see more.
+pub const MAX_LEVERAGE: u16 = 500;
+
+/// Maintenance margin tier for a position opened at `leverage`. Tiers only
+/// tighten as leverage rises.
+pub fn maintenance_margin(leverage: u16) -> f64 {
+    match leverage {
+        0..=20 => 0.025,
+        21..=50 => 0.01,
+        51..=100 => 0.005,
+        _ => 0.0025,
+    }
+}
+
+/// Errors unless `leverage` is in `1..=MAX_LEVERAGE`.
+pub fn check_leverage(leverage: u16) -> anyhow::Result<()> {
+    if leverage == 0 || leverage > MAX_LEVERAGE {
+        anyhow::bail!("leverage {} is outside 1..={}", leverage, MAX_LEVERAGE);
+    }
+    Ok(())
+}
+
+/// Margin needed to open `notional` at `leverage`.
+pub fn initial_margin(notional: i128, leverage: u16) -> i64 {
+    (notional / leverage.max(1) as i128) as i64
+}
+
+/// Notional over equity at `mark`, or `None` once equity is gone.
+pub fn effective_leverage(pos: &Position, mark: i64) -> Option<f64> {
+    let equity = pos.margin as i128 + pos.unrealized_pnl(mark);
+    if equity <= 0 { return None; }
+    Some((pos.size as i128 * mark as i128) as f64 / equity as f64)
+}
+
+/// (margin + unrealized) / notional, or `None` for an empty position.
+pub fn margin_ratio(pos: &Position, mark: i64) -> Option<f64> {
+    let pos_value = (pos.size as i128) * (mark as i128);
+    if pos_value <= 0 { return None; }
+    Some((pos.margin as i128 + pos.unrealized_pnl(mark)) as f64 / pos_value as f64)
+}
+
+/// Equity left in the position after closing `reduction` units at `mark`.
+/// The closed part's PnL is realized into margin, which stays with the
+/// remainder.
+pub fn equity_after_reduction(pos: &Position, mark: i64, reduction: i64) -> i128 {
+    let mut rest = pos.clone();
+    rest.realize(reduction, mark);
+    rest.margin as i128 + rest.unrealized_pnl(mark)
+}
+
+/// Smallest reduction (at least 1, at most `pos.size`) after which the
+/// remaining position is back at or above `target`, net of a penalty of
+/// `penalty_bps` on the closed notional. Returns `pos.size` if no partial
+/// cut gets there.
+pub fn reduction_to_target(pos: &Position, mark: i64, target: f64, penalty_bps: u32) -> i64 {
+    let healthy = |r: i64| {
+        let rest = (pos.size - r) as i128 * mark as i128;
+        if rest <= 0 { return true; }
+        let penalty = r as i128 * mark as i128 * penalty_bps as i128 / 10_000;
+        (equity_after_reduction(pos, mark, r) - penalty) as f64 / rest as f64 >= target
+    };
+
+    if pos.size <= 1 || !healthy(pos.size - 1) { return pos.size.max(0); }
+
+    let (mut lo, mut hi) = (1, pos.size - 1);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if healthy(mid) { hi = mid; } else { lo = mid + 1; }
+    }
+    lo
+}
@@ -0,0 +1,294 @@
+use std::fmt::Write;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+use crate::engine::backtest::{Replay, Snapshot};
+use crate::engine::config::{EngineConfig, MarketConfig};
+use crate::engine::models::{EngineEvent, LiquidationKind, LiquidationRecord, MarginMode, Position, SocializedLossRecord};
+
+/// A liquidation record a scenario expects. Each owner's expectations are
+/// matched in order against the records booked on its positions, and the
+/// counts must agree; fields left `None` match anything.
+#[derive(Clone, Debug, Default)]
+pub struct ExpectLiquidation {
+    pub owner: String,
+    pub kind: Option<LiquidationKind>,
+    pub size: Option<i64>,
+    pub price: Option<i64>,
+    pub bad_debt: Option<i64>,
+    /// Booked no later than this many ms into the scenario.
+    pub by_ms: Option<u64>,
+}
+
+impl ExpectLiquidation {
+    pub fn of(owner: &str) -> Self {
+        Self { owner: owner.to_string(), ..Default::default() }
+    }
+
+    pub fn kind(mut self, kind: LiquidationKind) -> Self { self.kind = Some(kind); self }
+    pub fn size(mut self, size: i64) -> Self { self.size = Some(size); self }
+    pub fn price(mut self, price: i64) -> Self { self.price = Some(price); self }
+    pub fn bad_debt(mut self, bad_debt: i64) -> Self { self.bad_debt = Some(bad_debt); self }
+    pub fn by(mut self, ms: u64) -> Self { self.by_ms = Some(ms); self }
+
+    fn mismatch(&self, at_ms: u64, r: &LiquidationRecord) -> Option<String> {
+        let mut diffs = Vec::new();
+        if self.kind.is_some_and(|k| k != r.kind) { diffs.push(format!("kind {:?} != {:?}", r.kind, self.kind.unwrap())); }
+        if self.size.is_some_and(|s| s != r.liquidated_size) { diffs.push(format!("size {} != {}", r.liquidated_size, self.size.unwrap())); }
+        if self.price.is_some_and(|p| p != r.liquidation_price) { diffs.push(format!("price {} != {}", r.liquidation_price, self.price.unwrap())); }
+        if self.bad_debt.is_some_and(|b| b != r.bad_debt) { diffs.push(format!("bad debt {} != {}", r.bad_debt, self.bad_debt.unwrap())); }
+        if self.by_ms.is_some_and(|ms| at_ms > ms) { diffs.push(format!("booked at {}ms, after {}ms", at_ms, self.by_ms.unwrap())); }
+        (!diffs.is_empty()).then(|| diffs.join(", "))
+    }
+}
+
+/// A liquidation sequence declared up front: markets, positions, a price
+/// path and what should come of it. `check` runs it against the real
+/// monitor, executor and funding logic on a simulated clock, and panics
+/// listing every expectation that didn't hold.
+pub struct Scenario {
+    config: EngineConfig,
+    snapshot: Snapshot,
+    /// (ms into the scenario, symbol, mark)
+    prices: Vec<(u64, String, i64)>,
+    step_ms: u64,
+    run_for_ms: u64,
+    liquidations: Vec<ExpectLiquidation>,
+    untouched: Vec<String>,
+    insurance: Vec<(u64, i64)>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            config: EngineConfig::default(),
+            snapshot: Snapshot::default(),
+            prices: Vec::new(),
+            step_ms: 1_000,
+            run_for_ms: 0,
+            liquidations: Vec::new(),
+            untouched: Vec::new(),
+            insurance: Vec::new(),
+        }
+    }
+}
+
+/// What a scenario run booked.
+#[derive(Clone, Debug, Default)]
+pub struct Outcome {
+    /// Liquidation records with the ms they were booked at.
+    pub records: Vec<(u64, LiquidationRecord)>,
+    pub haircuts: Vec<SocializedLossRecord>,
+    /// Total insurance balance after each pass.
+    pub insurance: Vec<(u64, i64)>,
+    pub positions: Vec<Position>,
+    /// Positions the insurance fund took over.
+    pub insurance_positions: Vec<Position>,
+}
+
+impl Outcome {
+    pub fn records_of(&self, owner: &str) -> Vec<&(u64, LiquidationRecord)> {
+        self.records.iter().filter(|(_, r)| r.position_owner == owner).collect()
+    }
+
+    /// Insurance balance after the last pass at or before `ms`.
+    pub fn insurance_at(&self, ms: u64) -> Option<i64> {
+        self.insurance.iter().take_while(|(t, _)| *t <= ms).last().map(|(_, b)| *b)
+    }
+
+    pub fn position_of(&self, owner: &str, symbol: &str) -> Option<&Position> {
+        self.positions.iter().find(|p| p.owner == owner && p.symbol == symbol)
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(mut self, f: impl FnOnce(&mut EngineConfig)) -> Self {
+        f(&mut self.config);
+        self
+    }
+
+    pub fn market(mut self, symbol: &str, market: MarketConfig) -> Self {
+        self.config.markets.insert(symbol.to_string(), market);
+        self
+    }
+
+    /// An isolated long of `size` at `entry_price`, backed by `margin`.
+    pub fn long(self, owner: &str, symbol: &str, size: i64, entry_price: i64, margin: i64, leverage: u16) -> Self {
+        self.isolated(owner, symbol, true, size, entry_price, margin, leverage)
+    }
+
+    pub fn short(self, owner: &str, symbol: &str, size: i64, entry_price: i64, margin: i64, leverage: u16) -> Self {
+        self.isolated(owner, symbol, false, size, entry_price, margin, leverage)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn isolated(self, owner: &str, symbol: &str, is_long: bool, size: i64, entry_price: i64, margin: i64, leverage: u16) -> Self {
+        self.position(Position {
+            id: Uuid::new_v4(),
+            owner: owner.to_string(),
+            symbol: symbol.to_string(),
+            size,
+            entry_price,
+            margin,
+            is_long,
+            leverage,
+            open: true,
+            realized_pnl: 0,
+            haircuts: 0,
+            margin_mode: MarginMode::Isolated,
+            liquidation: Default::default(),
+        })
+    }
+
+    /// A cross position, backed by `owner`'s account (see `deposit`).
+    pub fn cross(self, owner: &str, symbol: &str, is_long: bool, size: i64, entry_price: i64, leverage: u16) -> Self {
+        self.position(Position {
+            id: Uuid::new_v4(),
+            owner: owner.to_string(),
+            symbol: symbol.to_string(),
+            size,
+            entry_price,
+            margin: 0,
+            is_long,
+            leverage,
+            open: true,
+            realized_pnl: 0,
+            haircuts: 0,
+            margin_mode: MarginMode::Cross,
+            liquidation: Default::default(),
+        })
+    }
+
+    pub fn position(mut self, pos: Position) -> Self {
+        self.snapshot.positions.push(pos);
+        self
+    }
+
+    pub fn deposit(mut self, owner: &str, amount: i64) -> Self {
+        *self.snapshot.deposits.entry(owner.to_string()).or_default() += amount;
+        self
+    }
+
+    /// Sets `symbol`'s mark (and index) `at_ms` into the scenario.
+    pub fn price(mut self, at_ms: u64, symbol: &str, mark: i64) -> Self {
+        self.prices.push((at_ms, symbol.to_string(), mark));
+        self.run_for_ms = self.run_for_ms.max(at_ms);
+        self
+    }
+
+    /// Time between passes (default 1000).
+    pub fn step_ms(mut self, ms: u64) -> Self {
+        self.step_ms = ms.max(1);
+        self
+    }
+
+    /// Keeps running until `ms`, past the last price and expectation.
+    pub fn run_for(mut self, ms: u64) -> Self {
+        self.run_for_ms = self.run_for_ms.max(ms);
+        self
+    }
+
+    pub fn expect_liquidation(mut self, expected: ExpectLiquidation) -> Self {
+        if let Some(ms) = expected.by_ms { self.run_for_ms = self.run_for_ms.max(ms); }
+        self.liquidations.push(expected);
+        self
+    }
+
+    /// `owner` ends the scenario without a single liquidation record.
+    pub fn expect_untouched(mut self, owner: &str) -> Self {
+        self.untouched.push(owner.to_string());
+        self
+    }
+
+    /// Total insurance balance after the last pass at or before `at_ms`.
+    pub fn expect_insurance(mut self, at_ms: u64, balance: i64) -> Self {
+        self.insurance.push((at_ms, balance));
+        self.run_for_ms = self.run_for_ms.max(at_ms);
+        self
+    }
+
+    /// Runs the scenario without checking its expectations.
+    pub async fn run(&self) -> anyhow::Result<Outcome> {
+        let start: DateTime<Utc> = DateTime::from_timestamp(1_767_225_600, 0).expect("valid start"); // 2026-01-01
+        let mut replay = Replay::new(self.config.clone(), self.snapshot.clone(), start).await?;
+        let mut prices = self.prices.clone();
+        prices.sort_by_key(|p| p.0);
+
+        let mut outcome = Outcome::default();
+        let (mut ms, mut next) = (0, 0);
+        loop {
+            while let Some((_, symbol, mark)) = prices.get(next).filter(|p| p.0 <= ms) {
+                replay.state.oracle.set(symbol, *mark, None).await;
+                next += 1;
+            }
+            for event in replay.step(start + Duration::milliseconds(ms as i64)).await? {
+                match event {
+                    EngineEvent::Liquidation(e) => outcome.records.push((ms, e.record)),
+                    EngineEvent::SocializedLoss(e) => outcome.haircuts.push(e.record),
+                    _ => {}
+                }
+            }
+            outcome.insurance.push((ms, replay.insurance_balance().await));
+
+            if ms >= self.run_for_ms { break; }
+            ms = (ms + self.step_ms).min(self.run_for_ms);
+        }
+        outcome.positions = replay.state.positions.lock().await.clone();
+        outcome.insurance_positions = replay.state.insurance.lock().await.positions.clone();
+        Ok(outcome)
+    }
+
+    /// Every expectation `outcome` fails, one line each.
+    pub fn failures(&self, outcome: &Outcome) -> Vec<String> {
+        let mut failures = Vec::new();
+        let mut owners: Vec<&str> = Vec::new();
+        for e in &self.liquidations {
+            if !owners.contains(&e.owner.as_str()) { owners.push(&e.owner); }
+        }
+        for owner in owners {
+            let expected: Vec<_> = self.liquidations.iter().filter(|e| e.owner == owner).collect();
+            let actual = outcome.records_of(owner);
+            for (i, e) in expected.iter().enumerate() {
+                match actual.get(i) {
+                    Some((ms, r)) => if let Some(m) = e.mismatch(*ms, r) {
+                        failures.push(format!("{} liquidation #{}: {}", owner, i + 1, m));
+                    },
+                    None => failures.push(format!("{} liquidation #{}: never booked", owner, i + 1)),
+                }
+            }
+            if actual.len() > expected.len() {
+                failures.push(format!("{}: {} liquidations booked, {} expected", owner, actual.len(), expected.len()));
+            }
+        }
+        for owner in &self.untouched {
+            let n = outcome.records_of(owner).len();
+            if n > 0 { failures.push(format!("{}: {} liquidations booked, none expected", owner, n)); }
+        }
+        for &(ms, balance) in &self.insurance {
+            let actual = outcome.insurance_at(ms);
+            if actual != Some(balance) {
+                failures.push(format!("insurance at {}ms: {:?} != {}", ms, actual, balance));
+            }
+        }
+        failures
+    }
+
+    /// Runs the scenario and panics if any expectation fails.
+    pub async fn check(self) -> Outcome {
+        let outcome = self.run().await.expect("scenario runs");
+        let failures = self.failures(&outcome);
+        if !failures.is_empty() {
+            let mut msg = String::from("scenario failed:\n");
+            for f in &failures { let _ = writeln!(msg, "  {}", f); }
+            for (ms, r) in &outcome.records {
+                let _ = writeln!(msg, "  booked at {}ms: {} {:?} {} at {} (bad debt {})",
+                    ms, r.position_owner, r.kind, r.liquidated_size, r.liquidation_price, r.bad_debt);
+            }
+            panic!("{}", msg);
+        }
+        outcome
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::engine::config::EngineConfig;
+use crate::engine::liquidation_executor::step_size;
+use crate::engine::models::{LiquidationStatus, Position, WouldLiquidate};
+use crate::engine::risk;
+
+/// Would-liquidate decisions kept for `GET /shadow`.
+const RECENT: usize = 100;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SymbolShadowMetrics {
+    pub decisions: u64,
+    pub bankrupt: u64,
+    pub notional: i64,
+}
+
+/// What the executor would have liquidated in shadow mode. Positions'
+/// own liquidation status is left alone, so the book keeps its own copy
+/// to apply the same grace period, cooldown and steps-per-window limits.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ShadowBook {
+    #[serde(skip)]
+    statuses: HashMap<Uuid, LiquidationStatus>,
+    pub passes: u64,
+    pub decisions: u64,
+    pub notional: i64,
+    pub symbols: BTreeMap<String, SymbolShadowMetrics>,
+    pub recent: VecDeque<WouldLiquidate>,
+}
+
+impl ShadowBook {
+    /// The step a pass would take on `view` (a position seen through its
+    /// account) at `mark`, if any. `account_cut` is set once the pass has
+    /// already cut the position's cross account.
+    pub fn decide(
+        &mut self,
+        cfg: &EngineConfig,
+        view: &Position,
+        mark: i64,
+        now: DateTime<Utc>,
+        account_cut: bool,
+    ) -> Option<WouldLiquidate> {
+        let margin_ratio = risk::margin_ratio(view, mark)?;
+        let mm = risk::maintenance_margin(view.leverage);
+        let market = cfg.market(&view.symbol);
+        let target = market.target_ratio(view.leverage, mm);
+
+        let status = self.statuses.entry(view.id).or_insert_with(|| view.liquidation.clone());
+        status.observe(now, margin_ratio, mm, target);
+        let deferred = margin_ratio >= 0.0
+            && (!status.can_step(now, market) || status.in_grace(now, cfg.liquidators.grace_ms));
+        if margin_ratio >= mm || deferred || account_cut {
+            return None;
+        }
+
+        let bankrupt = margin_ratio < 0.0;
+        let size = if bankrupt { view.size } else { step_size(cfg, view, mark) };
+        if size <= 0 { return None; }
+        status.record_step(now, market, size >= view.size);
+
+        Some(WouldLiquidate {
+            id: Uuid::new_v4(),
+            position_id: view.id,
+            position_owner: view.owner.clone(),
+            symbol: view.symbol.clone(),
+            is_long: view.is_long,
+            margin_mode: view.margin_mode,
+            position_size: view.size,
+            size,
+            mark,
+            notional: (size as i128 * mark as i128) as i64,
+            margin_ratio,
+            maintenance_margin: mm,
+            target_ratio: target,
+            bankrupt,
+            timestamp: now,
+        })
+    }
+
+    pub fn record(&mut self, decision: &WouldLiquidate) {
+        self.decisions += 1;
+        self.notional = self.notional.saturating_add(decision.notional);
+        let symbol = self.symbols.entry(decision.symbol.clone()).or_default();
+        symbol.decisions += 1;
+        symbol.bankrupt += decision.bankrupt as u64;
+        symbol.notional = symbol.notional.saturating_add(decision.notional);
+
+        self.recent.push_front(decision.clone());
+        self.recent.truncate(RECENT);
+    }
+
+    /// Counts a finished pass and forgets positions that are no longer open.
+    pub fn end_pass<'a>(&mut self, positions: impl IntoIterator<Item = &'a Position>) {
+        let open: Vec<Uuid> = positions.into_iter().filter(|p| p.open).map(|p| p.id).collect();
+        self.statuses.retain(|id, _| open.contains(id));
+        self.passes += 1;
+    }
+}
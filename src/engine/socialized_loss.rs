@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::engine::config::SocializedLossMode;
+use crate::engine::models::{Position, SocializedLossRecord};
+
+/// Splits `deficit` across `candidates` given as (id, capacity, weight),
+/// pro-rata to weight and never exceeding a candidate's capacity.
+/// Returns the non-zero shares; their sum is below `deficit` only when
+/// total capacity runs out.
+pub fn allocate(deficit: i64, candidates: &[(Uuid, i64, i128)]) -> Vec<(Uuid, i64)> {
+    let mut taken = vec![0i64; candidates.len()];
+    let mut remaining = deficit;
+
+    loop {
+        let open: Vec<usize> = (0..candidates.len())
+            .filter(|&i| taken[i] < candidates[i].1 && candidates[i].2 > 0)
+            .collect();
+        if remaining <= 0 || open.is_empty() { break; }
+
+        let total_weight: i128 = open.iter().map(|&i| candidates[i].2).sum();
+        let mut progressed = 0i64;
+        for &i in &open {
+            let share = ((remaining as i128 * candidates[i].2) / total_weight) as i64;
+            let share = share.min(candidates[i].1 - taken[i]);
+            taken[i] += share;
+            progressed += share;
+        }
+        remaining -= progressed;
+
+        // rounding dust: hand out one unit at a time
+        if progressed == 0 {
+            for &i in &open {
+                if remaining == 0 { break; }
+                taken[i] += 1;
+                remaining -= 1;
+            }
+        }
+    }
+
+    candidates
+        .iter()
+        .zip(taken)
+        .filter(|(_, t)| *t > 0)
+        .map(|(c, t)| (c.0, t))
+        .collect()
+}
+
+/// Haircuts profitable open positions in `symbol` to absorb `deficit` left
+/// over from the liquidation of `source_id`. Each haircut is taken from the
+/// winner's margin and capped at its unrealized profit at `mark`, less
+/// what earlier haircuts already took.
+/// Returns the haircut records and whatever could not be absorbed.
+pub fn socialize(
+    positions: &mut [Position],
+    symbol: &str,
+    source_id: Uuid,
+    deficit: i64,
+    mark: i64,
+    mode: SocializedLossMode,
+    now: DateTime<Utc>,
+) -> (Vec<SocializedLossRecord>, i64) {
+    let candidates: Vec<(Uuid, i64, i128)> = positions
+        .iter()
+        .filter(|p| p.open && p.symbol == symbol && p.id != source_id)
+        .filter_map(|p| {
+            let profit = p.unrealized_pnl(mark) - p.haircuts as i128;
+            if profit <= 0 { return None; }
+            let weight = match mode {
+                SocializedLossMode::PnlWeighted => profit,
+                SocializedLossMode::OpenInterestWeighted => (p.size as i128) * (mark as i128),
+            };
+            Some((p.id, profit.min(i64::MAX as i128) as i64, weight))
+        })
+        .collect();
+
+    let shares = allocate(deficit, &candidates);
+    let mut absorbed = 0i64;
+    let mut records = Vec::with_capacity(shares.len());
+
+    for (id, haircut) in shares {
+        let Some(pos) = positions.iter_mut().find(|p| p.id == id) else { continue };
+        let margin_before = pos.margin;
+        pos.margin = pos.margin.saturating_sub(haircut);
+        pos.haircuts = pos.haircuts.saturating_add(haircut);
+        absorbed += haircut;
+
+        records.push(SocializedLossRecord {
+            id: Uuid::new_v4(),
+            source_position_id: source_id,
+            position_id: pos.id,
+            position_owner: pos.owner.clone(),
+            symbol: pos.symbol.clone(),
+            haircut,
+            margin_before,
+            margin_after: pos.margin,
+            timestamp: now,
+        });
+    }
+
+    (records, deficit - absorbed)
+}
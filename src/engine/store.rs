@@ -0,0 +1,28 @@
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use sqlx::{PgPool, Postgres};
+
+/// Where the engine writes its history.
+#[derive(Clone, Debug)]
+pub enum Store {
+    Postgres(PgPool),
+    /// Keeps nothing: for backtests and tests, which read what they need
+    /// off the event stream.
+    Memory,
+}
+
+impl Store {
+    pub async fn execute(&self, query: Query<'_, Postgres, PgArguments>) -> Result<(), sqlx::Error> {
+        match self {
+            Store::Postgres(pool) => query.execute(pool).await.map(|_| ()),
+            Store::Memory => Ok(()),
+        }
+    }
+
+    pub async fn fetch_all(&self, query: Query<'_, Postgres, PgArguments>) -> Result<Vec<PgRow>, sqlx::Error> {
+        match self {
+            Store::Postgres(pool) => query.fetch_all(pool).await,
+            Store::Memory => Ok(Vec::new()),
+        }
+    }
+}
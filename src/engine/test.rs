@@ -1,6 +1,37 @@
 #[cfg(test)]
 mod tests {
-    use crate::engine::models::Position;
+    use crate::engine::accounts::{AccountError, Accounts};
+    use crate::engine::adl;
+    use crate::engine::auction::Auction;
+    use crate::engine::backstop::{BackstopError, BackstopPool};
+    use crate::engine::backtest::{self, Replay, Snapshot};
+    use crate::engine::clock::{Clock, SimClock};
+    use crate::engine::controls::{Controls, EngineMode};
+    use crate::engine::config::{
+        AuctionConfig, BackstopConfig, DiscountCurve, EngineConfig, InsuranceConfig, MarginCallConfig, MarketConfig, MarketInsuranceConfig, PenaltyConfig, SocializedLossMode,
+        ThrottleConfig,
+    };
+    use crate::engine::execution::{ExecutionVenue, MarkPriceVenue, OrderBook, SimulatedOrderBook};
+    use crate::engine::orders::{self, OrderError, OrderRequest};
+    use crate::engine::funding::{self, FundingBook};
+    use crate::engine::insurance::InsuranceFunds;
+    use crate::engine::liquidation_executor::step_size;
+    use crate::engine::liquidators::LiquidatorRegistry;
+    use crate::engine::margin_call::MarginCallTracker;
+    use crate::engine::risk;
+    use crate::engine::shadow::ShadowBook;
+    use crate::engine::models::{EngineEvent, LiquidationKind, LiquidationStage, LiquidationStatus, Liquidity, MarginMode, Position};
+    use crate::engine::socialized_loss::{allocate, socialize};
+    use crate::engine::throttle::{Backlogged, Throttle};
+    use crate::engine::waterfall::{
+        InsuranceFundStage, Liquidation, MarketCloseStage, SocializedLossStage, StageContext, Waterfall,
+    };
+    use crate::api::http::{self, CollateralRequest};
+    use axum::extract::{Path, State};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::Json;
+    use chrono::{Duration, Utc};
+    use std::collections::HashMap;
     use uuid::Uuid;
 
     #[test]
@@ -16,6 +47,10 @@ mod tests {
             is_long: true,
             leverage: 50,
             open: true,
+            realized_pnl: 0,
+            haircuts: 0,
+            margin_mode: Default::default(),
+            liquidation: Default::default(),
         };
 
         // mark price slightly below entry to create negative unrealized PnL
@@ -36,4 +71,1109 @@ mod tests {
         // For these numbers margin_ratio should be negative (thus below maintenance)
         assert!(margin_ratio < 0.01);
     }
+
+    fn position(owner: &str, size: i64, entry_price: i64, is_long: bool) -> Position {
+        Position {
+            id: Uuid::new_v4(),
+            owner: owner.to_string(),
+            symbol: "BTC-USD".to_string(),
+            size,
+            entry_price,
+            margin: 1_000_000,
+            is_long,
+            leverage: 10,
+            open: true,
+            realized_pnl: 0,
+            haircuts: 0,
+            margin_mode: Default::default(),
+            liquidation: Default::default(),
+        }
+    }
+
+    /// The books a waterfall runs against, with BTC-USD marked at `mark`.
+    struct Books {
+        positions: Vec<Position>,
+        accounts: Accounts,
+        insurance: InsuranceFunds,
+        backstop: BackstopPool,
+        marks: HashMap<String, i64>,
+    }
+
+    impl Books {
+        fn new(cfg: &EngineConfig, positions: Vec<Position>, mark: i64) -> Self {
+            Books {
+                positions,
+                accounts: Accounts::default(),
+                insurance: InsuranceFunds::from_config(&cfg.insurance),
+                backstop: BackstopPool::default(),
+                marks: HashMap::from([("BTC-USD".to_string(), mark)]),
+            }
+        }
+
+        fn ctx<'a>(&'a mut self, cfg: &'a EngineConfig, venue: &'a dyn ExecutionVenue) -> StageContext<'a> {
+            StageContext {
+                config: cfg,
+                positions: &mut self.positions,
+                accounts: &mut self.accounts,
+                insurance: &mut self.insurance,
+                backstop: &mut self.backstop,
+                venue,
+                marks: &self.marks,
+            }
+        }
+    }
+
+    #[test]
+    fn test_socialized_loss_allocation_respects_caps() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        // equal weights, but `a` can only absorb 10
+        let shares = allocate(100, &[(a, 10, 1), (b, 1_000, 1)]);
+        let get = |id| shares.iter().find(|s| s.0 == id).map(|s| s.1).unwrap_or(0);
+
+        assert_eq!(get(a), 10);
+        assert_eq!(get(b), 90);
+    }
+
+    #[test]
+    fn test_socialized_loss_haircuts_only_winners() {
+        let mark = 50_000;
+        let winner = position("winner", 10, 40_000, true); // +100_000
+        let loser = position("loser", 10, 45_000, false);  // -50_000
+        let source = Uuid::new_v4();
+        let mut positions = vec![winner.clone(), loser.clone()];
+
+        let (records, unabsorbed) = socialize(
+            &mut positions, "BTC-USD", source, 30_000, mark, SocializedLossMode::PnlWeighted, Utc::now(),
+        );
+
+        assert_eq!(unabsorbed, 0);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].position_id, winner.id);
+        assert_eq!(positions[0].margin, winner.margin - 30_000);
+        assert_eq!(positions[1].margin, loser.margin);
+
+        // the first haircut already took 30_000 of the 100_000 profit
+        let (_, unabsorbed) = socialize(
+            &mut positions, "BTC-USD", source, 150_000, mark, SocializedLossMode::OpenInterestWeighted, Utc::now(),
+        );
+        assert_eq!(unabsorbed, 80_000);
+        assert_eq!((positions[0].margin, positions[0].haircuts), (winner.margin - 100_000, 100_000));
+
+        // with the profit used up, later events take nothing
+        let (records, unabsorbed) = socialize(
+            &mut positions, "BTC-USD", source, 10_000, mark, SocializedLossMode::PnlWeighted, Utc::now(),
+        );
+        assert!(records.is_empty());
+        assert_eq!(unabsorbed, 10_000);
+    }
+
+    #[test]
+    fn test_adl_closes_top_ranked_counterparty_at_bankruptcy_price() {
+        let mark = 50_000;
+        let mut bankrupt = position("bankrupt", 10, 60_000, true);
+        bankrupt.margin = 20_000; // bankruptcy price 58_000
+        let mut low = position("low", 10, 55_000, false);
+        low.leverage = 2;
+        let mut high = position("high", 10, 55_000, false);
+        high.leverage = 20;
+        let mut positions = vec![bankrupt.clone(), low.clone(), high.clone()];
+        let marks = HashMap::from([("BTC-USD".to_string(), mark)]);
+        let mut accounts = Accounts::default();
+
+        let ranked = adl::ranking(&positions, &accounts, "BTC-USD", false, &marks);
+        assert_eq!(ranked[0].0, high.id);
+
+        let price = adl::bankruptcy_price(&bankrupt);
+        assert_eq!(price, 58_000);
+
+        let fills = adl::deleverage(&mut positions, &mut accounts, &bankrupt, 15, price, &marks);
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[0].position_id, fills[0].size), (high.id, 10));
+        assert_eq!((fills[1].position_id, fills[1].size), (low.id, 5));
+
+        // shorts realize (55_000 - 58_000) per unit at the bankruptcy price
+        assert!(!positions[2].open);
+        assert_eq!(positions[2].margin, high.margin - 30_000);
+        assert_eq!(positions[1].size, 5);
+    }
+
+    #[test]
+    fn test_adl_ranks_cross_counterparties_on_account_equity() {
+        let marks = HashMap::from([("BTC-USD".to_string(), 50_000)]);
+        let mut bankrupt = position("bankrupt", 10, 60_000, true);
+        bankrupt.margin = 20_000;
+        let isolated = position("iso", 10, 60_000, false); // +100_000 on 1_000_000
+        let mut cross = position("carol", 10, 60_000, false); // +100_000 on a 20_000 account
+        cross.margin = 0;
+        cross.margin_mode = MarginMode::Cross;
+        let mut positions = vec![bankrupt.clone(), isolated.clone(), cross.clone()];
+        let mut accounts = Accounts::default();
+        accounts.deposit("carol", 20_000).unwrap();
+
+        // scored on its share of the account, the cross short is far more levered
+        let ranked = adl::ranking(&positions, &accounts, "BTC-USD", false, &marks);
+        assert_eq!(ranked.iter().map(|r| r.0).collect::<Vec<_>>(), vec![cross.id, isolated.id]);
+        assert_eq!(ranked[0].1, 50.0);
+
+        // its PnL at the bankruptcy price lands in the account
+        let fills = adl::deleverage(&mut positions, &mut accounts, &bankrupt, 10, 58_000, &marks);
+        assert_eq!((fills.len(), fills[0].position_id, fills[0].realized), (1, cross.id, 20_000));
+        assert_eq!((positions[2].open, positions[2].margin), (false, 0));
+        assert_eq!(accounts.collateral("carol"), 40_000);
+        assert_eq!(positions[1].size, 10);
+    }
+
+    #[test]
+    fn test_penalty_split_between_liquidator_and_fund() {
+        let cfg = PenaltyConfig { rate_bps: 250, insurance_share_bps: 4_000 };
+
+        // 2.5% of 1_000_000 = 25_000, 40% of that to the fund
+        assert_eq!(cfg.split(1_000_000, i64::MAX), (15_000, 10_000));
+
+        // capped at remaining equity, nothing charged when underwater
+        assert_eq!(cfg.split(1_000_000, 5_000), (3_000, 2_000));
+        assert_eq!(cfg.split(1_000_000, -1), (0, 0));
+    }
+
+    #[test]
+    fn test_isolated_insurance_fund_falls_back_to_shared() {
+        let mut cfg = InsuranceConfig { shared_balance: 1_000, ..Default::default() };
+        cfg.markets.insert("DOGE-USD".into(), MarketInsuranceConfig {
+            initial_balance: 100,
+            max_shared_draw: Some(50),
+            sweep_above: Some(200),
+            ..Default::default()
+        });
+        let mut funds = InsuranceFunds::from_config(&cfg);
+        let marks = HashMap::new();
+
+        assert_eq!(funds.available("DOGE-USD", &marks), 150);
+        assert_eq!(funds.cover("DOGE-USD", 500, &marks), 150);
+        assert_eq!(funds.markets["DOGE-USD"].balance, 0);
+        assert_eq!(funds.shared.balance, 950);
+
+        // markets without a sub-fund draw on the shared fund directly
+        assert_eq!(funds.cover("BTC-USD", 100, &marks), 100);
+        assert_eq!(funds.shared.balance, 850);
+
+        // contributions past the sweep threshold spill into the shared fund
+        funds.credit("DOGE-USD", 300);
+        assert_eq!(funds.markets["DOGE-USD"].balance, 200);
+        assert_eq!(funds.shared.balance, 950);
+
+        // the 50 cap is spent, so later deficits only get the sub-fund
+        assert_eq!(funds.available("DOGE-USD", &marks), 200);
+        assert_eq!(funds.cover("DOGE-USD", 500, &marks), 200);
+        assert_eq!((funds.shared.balance, funds.markets["DOGE-USD"].shared_drawn), (950, 50));
+
+        // small draws add up to the cap as well
+        cfg.markets.insert("PEPE-USD".into(), MarketInsuranceConfig { max_shared_draw: Some(50), ..Default::default() });
+        let mut funds = InsuranceFunds::from_config(&cfg);
+        let draws: Vec<i64> = (0..3).map(|_| funds.cover("PEPE-USD", 30, &marks)).collect();
+        assert_eq!(draws, vec![30, 20, 0]);
+        assert_eq!(funds.shared.balance, 950);
+    }
+
+    #[test]
+    fn test_insurance_book_is_marked_and_unwound() {
+        let cfg = InsuranceConfig { shared_balance: 1_000, ..Default::default() };
+        let mut funds = InsuranceFunds::from_config(&cfg);
+        funds.take_over(&position("user", 10, 100, true), 10, 100);
+
+        // 400 down on the book: counted in the balance, and not drawn on
+        let marks = HashMap::from([("BTC-USD".to_string(), 60)]);
+        assert_eq!(funds.total_balance(&marks), 600);
+        assert_eq!(funds.available("BTC-USD", &marks), 600);
+        assert_eq!(funds.cover("BTC-USD", 1_000, &marks), 600);
+        assert_eq!(funds.shared.balance, 400);
+
+        // unwinding realizes the loss into the fund and empties the book
+        let closes = funds.unwind(&MarkPriceVenue, &marks);
+        assert_eq!(closes.iter().map(|c| (c.size, c.price, c.pnl)).collect::<Vec<_>>(), vec![(10, 60, -400)]);
+        assert_eq!((funds.shared.balance, funds.total_balance(&marks)), (0, 0));
+        assert!(funds.positions.is_empty());
+    }
+
+    #[test]
+    fn test_example_config_parses() {
+        let cfg = EngineConfig::from_json(include_str!("../../engine.example.json")).unwrap();
+        assert!(cfg.insurance.markets.contains_key("DOGE-USD"));
+    }
+
+    #[test]
+    fn test_market_override_layers_over_market_defaults() {
+        let cfg = EngineConfig::from_json(r#"{
+            "market_defaults": { "cooldown_ms": 9000, "max_leverage": 20, "book_level_size": 7 },
+            "markets": { "BTC-USD": { "max_step_bps": 2500, "max_leverage": 50 } }
+        }"#).unwrap();
+        let btc = cfg.market("BTC-USD");
+        assert_eq!((btc.max_step_bps, btc.max_leverage), (2_500, 50));
+        // left out of the override: the operator's defaults, not the built-in ones
+        assert_eq!((btc.cooldown_ms, btc.book_level_size), (9_000, 7));
+        assert_eq!(btc.target_buffer_bps, MarketConfig::default().target_buffer_bps);
+        assert_eq!(cfg.market("ETH-USD").max_leverage, 20);
+    }
+
+    #[test]
+    fn test_config_rejects_webhooks_the_client_cannot_reach() {
+        let with = |url: &str| EngineConfig::from_json(&format!(r#"{{ "margin_call": {{ "webhooks": ["{}"] }} }}"#, url));
+        assert!(with("http://localhost:9000/margin-calls").is_ok());
+        let err = with("https://hooks.example.com/margin-calls").unwrap_err();
+        assert!(err.to_string().contains("only http:// URLs"));
+        assert!(with("localhost:9000").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_leverage_past_the_top_tier_is_refused() {
+        // more leverage never buys a looser maintenance requirement
+        assert_eq!(risk::maintenance_margin(1_000), risk::maintenance_margin(risk::MAX_LEVERAGE));
+
+        assert!(EngineConfig::from_json(r#"{ "market_defaults": { "max_leverage": 500 } }"#).is_ok());
+        assert!(EngineConfig::from_json(r#"{ "market_defaults": { "max_leverage": 1000 } }"#).is_err());
+        assert!(EngineConfig::from_json(r#"{ "markets": { "BTC-USD": { "max_leverage": 0 } } }"#).is_err());
+
+        let mut pos = position("user", 10, 100_000, true);
+        pos.leverage = 1_000;
+        let snapshot = Snapshot { positions: vec![pos], ..Default::default() };
+        assert!(Replay::new(EngineConfig::default(), snapshot, Utc::now()).await.is_err());
+    }
+
+    #[test]
+    fn test_partial_liquidation_sized_to_target_ratio() {
+        let mut pos = position("user", 100, 100, true);
+        pos.margin = 1_100;
+        let mark = 90; // ratio 100 / 9_000 ~ 1.1%
+
+        // the closed part's loss is realized, so equity stays 100:
+        // 100 / ((100 - r) * 90) >= 5%  =>  r >= 77.8
+        assert_eq!(risk::reduction_to_target(&pos, mark, 0.05, 0), 78);
+        assert_eq!(risk::equity_after_reduction(&pos, mark, 78), 100);
+        let after = risk::equity_after_reduction(&pos, mark, 78) as f64 / (22.0 * 90.0);
+        assert!(after >= 0.05);
+
+        // unreachable target closes everything
+        assert_eq!(risk::reduction_to_target(&pos, mark, 50.0, 0), 100);
+
+        let market = MarketConfig { max_step_bps: 2_000, min_step: 5, ..Default::default() };
+        assert_eq!(market.cap_step(25, 100), 20);
+        assert_eq!(market.cap_step(1, 100), 5);
+        assert_eq!(market.cap_step(25, 3), 3);
+    }
+
+    #[test]
+    fn test_liquidation_stages_and_cooldown() {
+        let market = MarketConfig {
+            cooldown_ms: 1_000,
+            max_steps_per_window: 2,
+            step_window_ms: 10_000,
+            ..Default::default()
+        };
+        let t0 = Utc::now();
+        let mut st = LiquidationStatus::default();
+
+        st.observe(t0, 0.008, 0.005, 0.01);
+        assert_eq!(st.stage, LiquidationStage::MarginCall);
+
+        st.observe(t0, 0.004, 0.005, 0.01);
+        assert!(st.can_step(t0, &market));
+        st.record_step(t0, &market, false);
+        assert_eq!(st.stage, LiquidationStage::CoolingDown);
+        assert!(!st.can_step(t0 + Duration::milliseconds(500), &market));
+
+        // cooldown over, still underwater
+        let t1 = t0 + Duration::milliseconds(1_500);
+        st.observe(t1, 0.004, 0.005, 0.01);
+        assert_eq!(st.stage, LiquidationStage::PartiallyLiquidated);
+        st.record_step(t1, &market, false);
+
+        // window budget spent until it rolls over
+        let t2 = t1 + Duration::milliseconds(1_500);
+        assert!(!st.can_step(t2, &market));
+        assert!(st.can_step(t0 + Duration::milliseconds(10_000), &market));
+
+        st.observe(t2, 0.02, 0.005, 0.01);
+        assert_eq!(st.stage, LiquidationStage::Healthy);
+    }
+
+    #[test]
+    fn test_order_book_sweep_realizes_slippage() {
+        // bids at 9_990, 9_980, 9_970 with 10 each
+        let mut book = OrderBook::around(10_000, 3, 10, 10);
+
+        let fill = book.sweep(true, 25);
+        assert_eq!(fill.filled, 25);
+        assert_eq!(fill.vwap, (10 * 9_990 + 10 * 9_980 + 5 * 9_970) / 25);
+        assert_eq!(fill.slippage(true, 10_000), 25 * 10_000 - (10 * 9_990 + 10 * 9_980 + 5 * 9_970));
+
+        // only 5 left on the bid side
+        let fill = book.sweep(true, 20);
+        assert_eq!((fill.filled, fill.vwap), (5, 9_970));
+        assert_eq!(book.sweep(true, 1).filled, 0);
+
+        // asks untouched
+        assert_eq!(book.sweep(false, 10).vwap, 10_010);
+    }
+
+    #[test]
+    fn test_auction_discount_grows_until_timeout() {
+        let cfg = AuctionConfig {
+            start_discount_bps: 100,
+            max_discount_bps: 500,
+            timeout_ms: 10_000,
+            ..Default::default()
+        };
+        let t0 = Utc::now();
+        let long = Auction::new(&cfg, Uuid::new_v4(), "user", "BTC-USD", true, 10, t0);
+
+        assert_eq!(long.discount_bps(t0), 100);
+        assert_eq!(long.discount_bps(t0 + Duration::milliseconds(5_000)), 300);
+        assert_eq!(long.discount_bps(t0 + Duration::milliseconds(20_000)), 500);
+        assert!(long.expired(t0 + Duration::milliseconds(10_000)));
+
+        // a long is sold below mark, a short bought back above it
+        assert_eq!(long.price(10_000, t0), 9_900);
+        let short = Auction::new(&cfg, Uuid::new_v4(), "user", "BTC-USD", false, 10, t0);
+        assert_eq!(short.price(10_000, t0), 10_100);
+
+        let expo = Auction::new(
+            &AuctionConfig { curve: DiscountCurve::Exponential { half_life_ms: 1_000 }, ..cfg },
+            Uuid::new_v4(), "user", "BTC-USD", true, 10, t0,
+        );
+        assert_eq!(expo.discount_bps(t0 + Duration::milliseconds(1_000)), 300);
+    }
+
+    #[tokio::test]
+    async fn test_paused_symbol_holds_its_live_auctions() {
+        let mut cfg = EngineConfig::default();
+        cfg.auction.timeout_ms = 1_000;
+        let mut pos = position("user", 100, 100, true);
+        pos.margin = 1_100;
+        let snapshot = Snapshot { positions: vec![pos.clone()], ..Default::default() };
+        let t0 = Utc::now();
+        let mut replay = Replay::new(cfg, snapshot, t0).await.unwrap();
+        let state = replay.state.clone();
+        state.oracle.set("BTC-USD", 90, None).await;
+        let auction = Auction::new(&state.config.auction, pos.id, "user", "BTC-USD", true, 100, t0);
+        state.auctions.lock().await.push(auction.clone());
+        state.controls.lock().await.paused_symbols.insert("BTC-USD".to_string());
+        let fund = replay.insurance_balance().await;
+
+        // long past its timeout, the auction neither expires into the fund nor settles
+        replay.step(t0 + Duration::seconds(5)).await.unwrap();
+        assert!(state.auctions.lock().await[0].is_live());
+        assert_eq!(state.positions.lock().await[0].size, 100);
+        assert_eq!(replay.insurance_balance().await, fund);
+
+        let key = state.liquidators.lock().await.register("desk-a", Utc::now()).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", key.parse().unwrap());
+        let bid = http::post_auction_bid(State(state.clone()), Path(auction.id), headers).await;
+        assert_eq!(bid.err().map(|(s, _)| s), Some(StatusCode::SERVICE_UNAVAILABLE));
+
+        state.controls.lock().await.paused_symbols.clear();
+        replay.step(t0 + Duration::seconds(6)).await.unwrap();
+        assert!(!state.auctions.lock().await[0].is_live());
+        assert!(state.positions.lock().await[0].size < 100);
+    }
+
+    #[test]
+    fn test_liquidator_registration_and_rewards() {
+        let mut registry = LiquidatorRegistry::default();
+        let key = registry.register("desk-a", Utc::now()).unwrap();
+        assert!(registry.register("desk-a", Utc::now()).is_none());
+
+        assert_eq!(registry.authenticate(&key), Some("desk-a"));
+        assert_eq!(registry.authenticate("nope"), None);
+
+        registry.credit("desk-a", 250).unwrap();
+        let acct = registry.credit("desk-a", 100).unwrap();
+        assert_eq!((acct.balance, acct.takeovers), (350, 2));
+
+        // the executor isn't an account
+        assert!(registry.credit("executor", 100).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_account_owners_have_their_own_keys() {
+        let replay = Replay::new(EngineConfig::default(), Snapshot::default(), Utc::now()).await.unwrap();
+        let state = replay.state.clone();
+        let liquidator_key = state.liquidators.lock().await.register("alice", Utc::now()).unwrap();
+        let key = state.owner_keys.lock().await.issue("alice").unwrap();
+        assert!(state.owner_keys.lock().await.issue("alice").is_none());
+        let bob_key = state.owner_keys.lock().await.issue("bob").unwrap();
+
+        let deposit = |header: &'static str, key: String| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header, key.parse().unwrap());
+            let req = CollateralRequest { amount: 1_000, asset: None };
+            http::account_deposit(State(state.clone()), Path("alice".to_string()), headers, Json(req))
+        };
+        let status = |res: Result<_, (StatusCode, Json<serde_json::Value>)>| res.err().map(|(s, _)| s);
+        // a liquidator key under the same name is not an account key
+        assert_eq!(status(deposit("x-api-key", liquidator_key.clone()).await), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(deposit("x-account-key", liquidator_key).await), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(deposit("x-account-key", bob_key).await), Some(StatusCode::FORBIDDEN));
+        assert_eq!(status(deposit("x-account-key", key.clone()).await), None);
+
+        // rotating retires the old key
+        let rotated = state.owner_keys.lock().await.rotate("alice");
+        assert_eq!(status(deposit("x-account-key", key).await), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(deposit("x-account-key", rotated).await), None);
+    }
+
+    #[test]
+    fn test_backstop_pool_shares_track_pool_pnl() {
+        let mut marks = HashMap::from([("BTC-USD".to_string(), 100)]);
+        let mut pool = BackstopPool::default();
+
+        assert_eq!(pool.deposit("lp-a", 1_000, &marks), Ok(1_000));
+        assert_eq!(pool.deposit("lp-b", 1_000, &marks), Ok(1_000));
+        let cfg = BackstopConfig { max_leverage: 5, ..Default::default() };
+        assert_eq!(pool.capacity(&cfg, &marks, true, 100, 100), 100);
+
+        // takes over 50 longs at 100 and earns a 10 fee
+        let source = position("bankrupt", 80, 120, true);
+        pool.take_over(&source, 50, 100, 10);
+        assert_eq!(pool.capacity(&cfg, &marks, true, 100, 100), 50);
+        // at 110, 10% of the 2_010 NAV only covers 20 units of the 10 loss each
+        assert_eq!(pool.capacity(&cfg, &marks, true, 110, 100), 20);
+        assert_eq!(pool.capacity(&cfg, &marks, false, 110, 100), 50);
+
+        // price up 2 -> +100 for the pool
+        marks.insert("BTC-USD".to_string(), 102);
+        assert_eq!(pool.nav(&marks), 2_110);
+        assert_eq!(pool.pnl(&marks), 110);
+
+        // new money buys in at the higher share price
+        assert_eq!(pool.deposit("lp-c", 1_055, &marks), Ok(1_000));
+        assert_eq!(pool.withdraw("lp-a", 1_000, &marks), Ok(1_055));
+        assert_eq!(pool.withdraw("lp-a", 1, &marks), Err(BackstopError::InsufficientShares));
+
+        // unwinding closes the longs at mark and books the 100 into cash
+        let closes = pool.unwind(&MarkPriceVenue, &marks);
+        assert_eq!(closes.iter().map(|c| (c.size, c.price, c.pnl)).collect::<Vec<_>>(), vec![(50, 102, 100)]);
+        assert!(pool.positions.is_empty());
+        assert_eq!(pool.nav(&marks), pool.cash);
+    }
+
+    #[test]
+    fn test_waterfall_stages_in_isolation() {
+        let mark = 50_000;
+        let mut bankrupt = position("bankrupt", 10, 60_000, true);
+        bankrupt.margin = 20_000; // equity -80_000 at mark
+        let winner = position("winner", 10, 55_000, false); // +50_000 at mark
+
+        // fund only: it covers the whole deficit
+        let cfg = EngineConfig::default();
+        let mut books = Books::new(&cfg, vec![bankrupt.clone(), winner.clone()], mark);
+        let waterfall = Waterfall::new(vec![Box::new(MarketCloseStage), Box::new(InsuranceFundStage)]);
+        let mut liq = Liquidation::close_out(bankrupt.clone(), mark, Utc::now());
+        waterfall.run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+
+        assert_eq!((liq.remaining, liq.balance, liq.outcome.unabsorbed), (0, 0, 0));
+        assert!(!liq.position.open);
+        assert_eq!(liq.outcome.records.len(), 1);
+        assert_eq!(liq.outcome.records[0].bad_debt, 80_000);
+        assert_eq!(books.insurance.shared.balance, 920_000);
+
+        // empty fund, socialized loss only: the winner's profit caps the haircut
+        let cfg = EngineConfig {
+            insurance: InsuranceConfig { shared_balance: 0, markets: HashMap::new() },
+            ..Default::default()
+        };
+        let mut books = Books::new(&cfg, vec![bankrupt.clone(), winner.clone()], mark);
+        let waterfall = Waterfall::new(vec![Box::new(InsuranceFundStage), Box::new(SocializedLossStage)]);
+        let mut liq = Liquidation::close_out(bankrupt, mark, Utc::now());
+        waterfall.run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+
+        assert_eq!(liq.outcome.haircuts.len(), 1);
+        assert_eq!(liq.outcome.unabsorbed, 30_000);
+        assert_eq!(books.positions[1].margin, winner.margin - 50_000);
+        // nobody took the size, so it was closed at mark after the last stage
+        assert_eq!(liq.outcome.records[0].liquidation_price, mark);
+    }
+
+    #[test]
+    fn test_default_waterfall_deleverages_when_fund_is_short() {
+        let mark = 50_000;
+        let mut bankrupt = position("bankrupt", 10, 60_000, true);
+        bankrupt.margin = 20_000;
+        let winner = position("winner", 10, 55_000, false);
+
+        // an empty book: the venue can't absorb any of it
+        let cfg = EngineConfig {
+            insurance: InsuranceConfig { shared_balance: 10_000, markets: HashMap::new() },
+            market_defaults: MarketConfig { book_level_size: 0, ..Default::default() },
+            ..Default::default()
+        };
+        let venue = SimulatedOrderBook::new(cfg.clone());
+        let mut books = Books::new(&cfg, vec![bankrupt.clone(), winner.clone()], mark);
+        let mut liq = Liquidation::close_out(bankrupt.clone(), mark, Utc::now());
+        assert_eq!(liq.bankruptcy_price(), 58_000);
+        Waterfall::from_config(&cfg.waterfall).run(&mut books.ctx(&cfg, &venue), &mut liq);
+
+        // closed against the winner at the bankruptcy price: no deficit left
+        let kinds: Vec<LiquidationKind> = liq.outcome.records.iter().map(|r| r.kind).collect();
+        assert_eq!(kinds, vec![LiquidationKind::Adl, LiquidationKind::Full]);
+        assert_eq!((liq.balance, liq.outcome.unabsorbed, liq.insurance_covered), (0, 0, 0));
+        assert!(!books.positions[1].open);
+        assert_eq!(books.positions[1].margin, winner.margin - 30_000);
+        assert_eq!(books.insurance.shared.balance, 10_000);
+
+        // a venue that takes it all leaves the winner alone
+        let mut books = Books::new(&cfg, vec![bankrupt.clone(), winner.clone()], mark);
+        let mut liq = Liquidation::close_out(bankrupt, mark, Utc::now());
+        Waterfall::from_config(&cfg.waterfall).run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+        assert_eq!(liq.outcome.records.iter().map(|r| r.kind).collect::<Vec<_>>(), vec![LiquidationKind::Full]);
+        assert_eq!((liq.insurance_covered, books.insurance.shared.balance), (10_000, 0));
+        assert!(books.positions[1].open);
+    }
+
+    #[test]
+    fn test_waterfall_step_charges_penalty() {
+        let mark = 100;
+        let cfg = EngineConfig::default();
+        let mut books = Books::new(&cfg, Vec::new(), mark);
+
+        let pos = position("trader", 100, 100, true);
+        let mut liq = Liquidation::step(pos.clone(), 40, mark, Utc::now());
+        Waterfall::from_config(&cfg.waterfall).run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+
+        // 2.5% of 4_000, half each to the liquidator and the fund
+        let rec = &liq.outcome.records[0];
+        assert_eq!((rec.kind, rec.liquidated_size), (LiquidationKind::Partial, 40));
+        assert_eq!((rec.liquidator_reward, rec.insurance_contribution), (50, 50));
+        assert_eq!(liq.outcome.contributions, vec![50]);
+        // plus the 25 bps liquidation fee, owed to the fee account
+        assert_eq!((rec.liquidation_fee, rec.fee_tier.as_str(), liq.outcome.fees), (10, "default", 10));
+        assert_eq!((liq.position.size, liq.position.margin), (60, pos.margin - 110));
+        assert!(liq.position.open);
+        assert_eq!(books.insurance.shared.balance, 1_000_050);
+    }
+
+    #[test]
+    fn test_margin_call_warnings_use_hysteresis() {
+        // warn at 0.075 and 0.06, re-arm above 0.0825 and 0.066
+        let cfg = MarginCallConfig::default();
+        let mm = 0.05;
+        let id = Uuid::new_v4();
+        let mut tracker = MarginCallTracker::default();
+        let mut observe = |ratio: f64| tracker.observe(&cfg, id, ratio, mm);
+
+        assert_eq!(observe(0.08), None);
+        assert_eq!(observe(0.07), Some(1.5));
+        // bouncing around the threshold doesn't warn again
+        assert_eq!(observe(0.076), None);
+        assert_eq!(observe(0.074), None);
+        assert_eq!(observe(0.059), Some(1.2));
+        assert_eq!(observe(0.065), None);
+        // back above 0.066 re-arms the second warning only
+        assert_eq!(observe(0.07), None);
+        assert_eq!(observe(0.059), Some(1.2));
+        assert_eq!(observe(0.09), None);
+        assert_eq!(observe(0.07), Some(1.5));
+    }
+
+    #[test]
+    fn test_cross_margin_account_shares_collateral() {
+        // a hedged account: -1_000 on the long, +1_000 on the short
+        let mut long = position("carol", 100, 100, true);
+        let mut short = position("carol", 100, 100, false);
+        short.symbol = "ETH-USD".into();
+        for p in [&mut long, &mut short] {
+            p.margin = 0;
+            p.margin_mode = MarginMode::Cross;
+        }
+        let cfg = EngineConfig::default();
+        let mut books = Books::new(&cfg, vec![long.clone(), short.clone()], 90);
+        books.marks.insert("ETH-USD".to_string(), 90);
+        books.accounts.deposit("carol", 400).unwrap();
+
+        // each leg needs 225; 400 of equity is short of 450
+        assert_eq!(books.accounts.equity("carol", &books.positions, &books.marks), 400);
+        assert_eq!(books.accounts.requirement("carol", &books.positions, &books.marks), 450);
+        let view = books.accounts.view(&books.positions[0], &books.positions, &books.marks);
+        assert_eq!(view.margin, 1_200);
+        assert!(risk::margin_ratio(&view, 90).unwrap() < risk::maintenance_margin(10));
+        // the same long on its own, with the collateral as margin, is underwater
+        assert!(risk::margin_ratio(&Position { margin: 400, ..long.clone() }, 90).unwrap() < 0.0);
+
+        // cut the long: its realized loss, the penalty and the fee land on the account
+        books.accounts.lend(&mut books.positions, 0, &books.marks);
+        assert_eq!((books.positions[0].margin, books.accounts.collateral("carol")), (1_200, -800));
+        let mut liq = Liquidation::step(books.positions[0].clone(), 50, 90, Utc::now());
+        Waterfall::new(vec![Box::new(MarketCloseStage)]).run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+        assert_eq!(liq.outcome.records[0].liquidator_reward + liq.outcome.records[0].insurance_contribution, 112);
+
+        books.positions[0] = liq.position;
+        let released = books.positions[0].margin;
+        books.accounts.settle(&mut books.positions[0], released);
+        assert_eq!(books.positions[0].margin, 0);
+        assert_eq!(liq.outcome.records[0].liquidation_fee, 11);
+        assert_eq!(books.accounts.equity("carol", &books.positions, &books.marks), 400 - 112 - 11);
+        assert_eq!(books.accounts.withdraw("carol", 1, &books.positions, &books.marks), Err(AccountError::InsufficientCollateral));
+    }
+
+    #[test]
+    fn test_collateral_haircuts_caps_and_sales() {
+        let marks = HashMap::from([("BTC-USD".to_string(), 90), ("ETH-USD".to_string(), 90)]);
+        let mut pos = position("dave", 100, 100, true);
+        pos.margin = 0;
+        pos.margin_mode = MarginMode::Cross;
+        let positions = vec![pos];
+        let cfg = EngineConfig::default();
+        let mut accounts = Accounts::from_config(&cfg);
+        assert_eq!(accounts.deposit_asset("dave", "DOGE", 1), Err(AccountError::UnknownAsset));
+        accounts.deposit_asset("dave", "BTC", 10).unwrap();
+        accounts.deposit_asset("dave", "ETH", 4).unwrap();
+
+        // BTC: 900 less 10% = 810, capped at 60% of 1_116; ETH: 360 less 15% = 306
+        assert_eq!(accounts.collateral_value("dave", &marks), 669 + 306);
+        assert!(accounts.equity("dave", &positions, &marks) < accounts.requirement("dave", &positions, &marks));
+
+        // the ETH goes first; it isn't enough, so the BTC follows
+        let sales = accounts.sell_collateral("dave", &positions, &marks, &MarkPriceVenue, Utc::now());
+        let sold: Vec<_> = sales.iter().map(|s| (s.asset.as_str(), s.quantity, s.proceeds)).collect();
+        assert_eq!(sold, vec![("ETH", 4, 360), ("BTC", 10, 900)]);
+        assert_eq!(accounts.collateral("dave"), 1_260);
+        assert!(accounts.equity("dave", &positions, &marks) >= accounts.requirement("dave", &positions, &marks));
+        assert!(accounts.sell_collateral("dave", &positions, &marks, &MarkPriceVenue, Utc::now()).is_empty());
+    }
+
+    #[test]
+    fn test_portfolio_margin_nets_hedged_exposure() {
+        let marks = HashMap::from([("BTC-USD".to_string(), 90), ("ETH-USD".to_string(), 90)]);
+        let mut long = position("erin", 100, 90, true);
+        let mut short = position("erin", 100, 90, false);
+        short.symbol = "ETH-USD".into();
+        for p in [&mut long, &mut short] {
+            p.margin = 0;
+            p.margin_mode = MarginMode::Cross;
+        }
+        let positions = vec![long, short];
+        let mut cfg = EngineConfig::default();
+
+        // charged as unrelated: 225 per leg
+        let mut accounts = Accounts::from_config(&cfg);
+        accounts.deposit("erin", 200).unwrap();
+        assert_eq!(accounts.requirement("erin", &positions, &marks), 450);
+        let view = accounts.view(&positions[0], &positions, &marks);
+        assert!(risk::margin_ratio(&view, 90).unwrap() < risk::maintenance_margin(10));
+
+        // BTC and ETH shocked together: the legs net out, leaving the 50 bps floor
+        cfg.portfolio_margin.enabled = true;
+        let mut accounts = Accounts::from_config(&cfg);
+        accounts.deposit("erin", 200).unwrap();
+        assert_eq!(accounts.requirement("erin", &positions, &marks), 90);
+        let view = accounts.view(&positions[0], &positions, &marks);
+        assert!(risk::margin_ratio(&view, 90).unwrap() >= risk::maintenance_margin(10));
+        assert_eq!(accounts.withdraw("erin", 111, &positions, &marks), Err(AccountError::InsufficientCollateral));
+
+        // shocked independently, each leg's worst move is 5% of 9_000
+        cfg.portfolio_margin.groups.clear();
+        let accounts = Accounts::from_config(&cfg);
+        assert_eq!(accounts.requirement("erin", &positions, &marks), 900);
+    }
+
+    #[test]
+    fn test_funding_settles_into_margin_and_collateral() {
+        let cfg = EngineConfig::default().funding;
+        let marks = HashMap::from([("BTC-USD".to_string(), 100_200), ("ETH-USD".to_string(), 2_000)]);
+        let index = HashMap::from([("BTC-USD".to_string(), 100_000), ("ETH-USD".to_string(), 2_000)]);
+
+        // a 20 bps premium averages to 20 bps, capped at 75 bps
+        let mut book = FundingBook::default();
+        book.sample(&marks, &index);
+        book.sample(&HashMap::from([("BTC-USD".to_string(), 100_000)]), &index);
+        assert!((book.estimate(&cfg, "BTC-USD").unwrap() - 0.001).abs() < 1e-9);
+        book.sample(&HashMap::from([("BTC-USD".to_string(), 200_000)]), &index);
+        assert_eq!(book.estimate(&cfg, "BTC-USD"), Some(0.0075));
+
+        let mut positions = vec![position("frank", 10, 100_000, true), position("grace", 10, 100_000, false)];
+        positions[1].margin_mode = MarginMode::Cross;
+        let margin = positions[0].margin;
+        let mut accounts = Accounts::default();
+        let rates = book.close(&cfg, &marks, &index, Utc::now());
+        assert!(book.estimate(&cfg, "BTC-USD").is_none());
+        assert_eq!(rates.len(), 2);
+
+        // longs pay shorts when the mark trades above the index
+        let payments = funding::settle(&mut positions, &mut accounts, &rates, Utc::now());
+        let owed = (10.0 * 100_200.0 * 0.0075) as i64;
+        assert_eq!(payments.iter().map(|p| p.amount).collect::<Vec<_>>(), vec![-owed, owed]);
+        assert_eq!(positions[0].margin, margin - owed);
+        assert_eq!(accounts.collateral("grace"), owed);
+    }
+
+    fn order(owner: &str, size: i64, margin: i64, liquidity: Liquidity) -> OrderRequest {
+        OrderRequest {
+            owner: owner.into(),
+            symbol: "BTC-USD".into(),
+            is_long: true,
+            size,
+            margin,
+            leverage: None,
+            margin_mode: MarginMode::Isolated,
+            liquidity,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_orders_need_the_owners_account_key() {
+        let replay = Replay::new(EngineConfig::default(), Snapshot::default(), Utc::now()).await.unwrap();
+        let state = replay.state.clone();
+        state.oracle.set("BTC-USD", 100_000, None).await;
+        let key = state.owner_keys.lock().await.issue("alice").unwrap();
+        let bob_key = state.owner_keys.lock().await.issue("bob").unwrap();
+
+        let place = |key: Option<String>| {
+            let mut headers = HeaderMap::new();
+            if let Some(key) = key { headers.insert("x-account-key", key.parse().unwrap()); }
+            let req = order("alice", 1, 100_000, Liquidity::Taker);
+            http::post_position(State(state.clone()), headers, Json(req))
+        };
+        let status = |res: Result<_, (StatusCode, Json<serde_json::Value>)>| res.err().map(|(s, _)| s);
+        assert_eq!(status(place(None).await), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(place(Some("nope".into())).await), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(place(Some(bob_key)).await), Some(StatusCode::FORBIDDEN));
+        assert!(state.positions.lock().await.is_empty());
+
+        assert_eq!(status(place(Some(key)).await), None);
+        assert_eq!(state.positions.lock().await[0].owner, "alice");
+    }
+
+    #[test]
+    fn test_orders_pay_tiered_fees() {
+        let mut cfg = EngineConfig::default();
+        cfg.fees.accounts.insert("hank".into(), "vip".into());
+        let mut positions = Vec::new();
+        let mut accounts = Accounts::default();
+        let now = Utc::now();
+        let at = |mark: i64| HashMap::from([("BTC-USD".to_string(), mark)]);
+
+        // 5 bps taker on 10_000 of notional comes out of the posted margin
+        let t = orders::fill(&cfg, &mut positions, &mut accounts, &order("ivan", 100, 1_000, Liquidity::Taker), &at(100), now).unwrap();
+        assert_eq!((t.fee, t.fee_tier.as_str(), positions[0].margin), (5, "default", 995));
+        // adding at a new price averages the entry; the vip maker rate is free
+        orders::fill(&cfg, &mut positions, &mut accounts, &order("hank", 100, 1_000, Liquidity::Maker), &at(100), now).unwrap();
+        assert_eq!(positions[1].margin, 1_000);
+        let t = orders::fill(&cfg, &mut positions, &mut accounts, &order("ivan", 100, 1_000, Liquidity::Maker), &at(200), now).unwrap();
+        assert_eq!((t.position_id, t.fee), (positions[0].id, 4));
+        assert_eq!((positions[0].size, positions[0].entry_price, positions[0].margin), (200, 150, 1_991));
+
+        assert_eq!(
+            orders::fill(&cfg, &mut positions, &mut accounts, &order("ivan", 100, 5, Liquidity::Taker), &at(100), now).unwrap_err(),
+            OrderError::InsufficientMargin,
+        );
+        // cross orders pay out of the account
+        accounts.deposit("judy", 1_000).unwrap();
+        let mut cross = order("judy", 100, 0, Liquidity::Taker);
+        cross.margin_mode = MarginMode::Cross;
+        orders::fill(&cfg, &mut positions, &mut accounts, &cross, &at(100), now).unwrap();
+        assert_eq!(accounts.collateral("judy"), 995);
+    }
+
+    #[test]
+    fn test_orders_enforce_initial_margin_and_leverage() {
+        let cfg = EngineConfig::default();
+        let marks = HashMap::from([("BTC-USD".to_string(), 100)]);
+        let mut positions = Vec::new();
+        let mut accounts = Accounts::default();
+        let now = Utc::now();
+
+        // leverage comes from notional over margin, not from the order
+        orders::fill(&cfg, &mut positions, &mut accounts, &order("liam", 100, 1_005, Liquidity::Taker), &marks, now).unwrap();
+        assert_eq!(positions[0].leverage, 10);
+        assert_eq!(risk::effective_leverage(&positions[0], 100), Some(10.0));
+        // 10_000 on 45 of margin is past the 100x max
+        assert_eq!(
+            orders::fill(&cfg, &mut positions, &mut accounts, &order("mia", 100, 50, Liquidity::Taker), &marks, now).unwrap_err(),
+            OrderError::ExceedsMaxLeverage,
+        );
+        assert_eq!(positions.len(), 1);
+
+        let mut cross = order("noah", 100, 0, Liquidity::Taker);
+        cross.margin_mode = MarginMode::Cross;
+        cross.leverage = Some(200);
+        assert_eq!(orders::fill(&cfg, &mut positions, &mut accounts, &cross, &marks, now).unwrap_err(), OrderError::ExceedsMaxLeverage);
+        // at 10x the account needs 1_000 of initial margin plus the fee
+        cross.leverage = Some(10);
+        accounts.deposit("noah", 1_000).unwrap();
+        assert_eq!(orders::fill(&cfg, &mut positions, &mut accounts, &cross, &marks, now).unwrap_err(), OrderError::InsufficientMargin);
+        accounts.deposit("noah", 5).unwrap();
+        orders::fill(&cfg, &mut positions, &mut accounts, &cross, &marks, now).unwrap();
+        assert_eq!((positions[1].leverage, accounts.collateral("noah")), (10, 1_000));
+        assert_eq!(accounts.initial_requirement("noah", &positions, &marks), 1_000);
+    }
+
+    #[test]
+    fn test_partial_step_realizes_pnl_into_margin() {
+        let mark = 90;
+        let mut cfg = EngineConfig::default();
+        cfg.penalty.rate_bps = 0;
+        cfg.fees.default_tier.liquidation_bps = 0;
+        let mut books = Books::new(&cfg, Vec::new(), mark);
+
+        let mut pos = position("kim", 100, 100, true);
+        pos.margin = 1_500;
+        let mut liq = Liquidation::step(pos, 40, mark, Utc::now());
+        Waterfall::new(vec![Box::new(MarketCloseStage)]).run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+
+        // 40 closed at a 10 loss each: margin takes the 400, the rest stays unrealized
+        let rec = &liq.outcome.records[0];
+        assert_eq!((rec.realized_pnl, rec.unrealized_pnl), (-400, -600));
+        assert_eq!((rec.margin_before, rec.margin_after), (500, 500));
+        assert_eq!((liq.position.margin, liq.position.realized_pnl), (1_100, -400));
+        assert_eq!(risk::margin_ratio(&liq.position, mark), Some(500.0 / (60.0 * 90.0)));
+    }
+
+    #[test]
+    fn test_close_out_settles_pnl_into_margin() {
+        let mark = 90;
+        let cfg = EngineConfig::default();
+        let waterfall = Waterfall::new(vec![Box::new(MarketCloseStage), Box::new(InsuranceFundStage)]);
+
+        // 1_000 lost on the close and the 25 bps fee on 9_000 come out of margin
+        let mut books = Books::new(&cfg, Vec::new(), mark);
+        let mut pos = position("lee", 100, 100, true);
+        pos.margin = 1_500;
+        let mut liq = Liquidation::close_out(pos, mark, Utc::now());
+        waterfall.run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+        assert_eq!(liq.outcome.records[0].liquidation_fee, 22);
+        assert_eq!((liq.position.size, liq.position.realized_pnl), (0, -1_000));
+        assert_eq!(liq.position.margin, 1_500 - 1_000 - 22);
+
+        // underwater: the fund covers the 500 deficit, leaving nothing
+        let mut pos = position("lee", 100, 100, true);
+        pos.margin = 500;
+        let mut liq = Liquidation::close_out(pos, mark, Utc::now());
+        waterfall.run(&mut books.ctx(&cfg, &MarkPriceVenue), &mut liq);
+        assert_eq!((liq.insurance_covered, liq.outcome.records[0].bad_debt), (500, 500));
+        assert_eq!((liq.position.margin, liq.position.realized_pnl), (0, -1_000));
+    }
+
+    #[test]
+    fn test_throttle_limits_notional_per_window() {
+        let cfg = ThrottleConfig {
+            enabled: true,
+            window_ms: 60_000,
+            max_notional: Some(10_000),
+            symbols: HashMap::from([("ETH-USD".to_string(), 3_000)]),
+        };
+        let t0 = Utc::now();
+        let mut throttle = Throttle::default();
+        assert_eq!(throttle.allowance(&ThrottleConfig::default(), "BTC-USD", t0), i64::MAX);
+
+        throttle.record("ETH-USD", 2_000, t0);
+        assert_eq!(throttle.allowance(&cfg, "ETH-USD", t0), 1_000);
+        throttle.record("BTC-USD", 7_500, t0 + Duration::seconds(30));
+        assert_eq!(throttle.allowance(&cfg, "BTC-USD", t0 + Duration::seconds(30)), 500);
+        assert_eq!(throttle.allowance(&cfg, "ETH-USD", t0 + Duration::seconds(30)), 500);
+        // the ETH liquidation drops out of the window first
+        assert_eq!(throttle.allowance(&cfg, "ETH-USD", t0 + Duration::seconds(61)), 2_500);
+        assert_eq!(throttle.used(None), 7_500);
+
+        // a queued position keeps its place in time across passes
+        let queued = |since| Backlogged {
+            position_id: Uuid::nil(),
+            position_owner: "olga".into(),
+            symbol: "BTC-USD".into(),
+            margin_ratio: 0.001,
+            notional: 5_000,
+            queued_since: since,
+        };
+        throttle.queue(vec![queued(t0)]);
+        throttle.queue(vec![queued(t0 + Duration::seconds(5))]);
+        assert_eq!(throttle.backlog[0].queued_since, t0);
+        throttle.queue(Vec::new());
+        assert!(throttle.backlog.is_empty());
+    }
+
+    #[test]
+    fn test_controls_gate_liquidations_and_orders() {
+        let mut controls = Controls::default();
+        assert!(controls.liquidates("BTC-USD") && controls.monitors() && controls.accepts_orders("BTC-USD"));
+
+        Controls::toggle(&mut controls.paused_symbols, "ETH-USD", Some(true));
+        Controls::toggle(&mut controls.reduce_only_symbols, "ETH-USD", Some(true));
+        assert!(!controls.liquidates("ETH-USD") && !controls.accepts_orders("ETH-USD"));
+        assert!(controls.liquidates("BTC-USD") && controls.accepts_orders("BTC-USD"));
+        Controls::toggle(&mut controls.paused_symbols, "ETH-USD", None);
+        assert!(!controls.liquidates("ETH-USD"));
+        Controls::toggle(&mut controls.paused_symbols, "ETH-USD", Some(false));
+        assert!(controls.liquidates("ETH-USD"));
+
+        // liquidations off, monitor on
+        controls.mode = EngineMode::MonitorOnly;
+        assert!(!controls.liquidates("BTC-USD") && controls.monitors());
+        controls.mode = EngineMode::Paused;
+        assert!(!controls.liquidates("BTC-USD") && !controls.monitors());
+
+        controls.reduce_only = true;
+        assert!(!controls.accepts_orders("BTC-USD"));
+        let json = serde_json::to_value(&controls).unwrap();
+        assert_eq!(json["mode"], "paused");
+    }
+
+    #[test]
+    fn test_shadow_book_decides_without_touching_positions() {
+        let cfg = EngineConfig {
+            market_defaults: MarketConfig { cooldown_ms: 1_000, ..Default::default() },
+            ..Default::default()
+        };
+        let mut pos = position("user", 100, 100, true);
+        pos.margin = 1_100;
+        let before = serde_json::to_value(&pos).unwrap();
+        let mut book = ShadowBook::default();
+        let t0 = Utc::now();
+
+        // same sizing as a real step
+        let d = book.decide(&cfg, &pos, 90, t0, false).unwrap();
+        assert_eq!(d.size, step_size(&cfg, &pos, 90));
+        assert_eq!(d.notional, d.size * 90);
+        assert!(!d.bankrupt);
+        book.record(&d);
+
+        // the shadow copy of its status is cooling down; the position's isn't
+        assert!(book.decide(&cfg, &pos, 90, t0 + Duration::milliseconds(500), false).is_none());
+        assert_eq!(serde_json::to_value(&pos).unwrap(), before);
+
+        // bankrupt positions skip the cooldown and close out in full
+        let d = book.decide(&cfg, &pos, 80, t0 + Duration::milliseconds(600), false).unwrap();
+        assert!(d.bankrupt);
+        assert_eq!(d.size, 100);
+        book.record(&d);
+
+        assert!(book.decide(&cfg, &position("other", 100, 100, true), 100, t0, false).is_none());
+        book.end_pass([&pos]);
+        assert_eq!((book.passes, book.decisions), (1, 2));
+        assert_eq!(book.symbols["BTC-USD"].bankrupt, 1);
+        assert_eq!(book.recent[0].position_id, pos.id);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_mode_pays_no_funding_and_takes_no_orders() {
+        let mut cfg = EngineConfig::default();
+        cfg.shadow.enabled = true;
+        cfg.funding.interval_secs = 60;
+        let pos = position("user", 10, 100_000, true);
+        let snapshot = Snapshot { positions: vec![pos.clone()], ..Default::default() };
+        let t0 = Utc::now();
+        let mut replay = Replay::new(cfg, snapshot, t0).await.unwrap();
+
+        // a 20 bps premium: the long would pay at settlement
+        replay.state.oracle.set("BTC-USD", 100_200, Some(100_000)).await;
+        let mut events = Vec::new();
+        for s in 0..=60 {
+            events.extend(replay.step(t0 + Duration::seconds(s)).await.unwrap());
+        }
+        let funding: Vec<_> = events.iter().filter_map(|e| match e {
+            EngineEvent::Funding(f) => Some(f),
+            _ => None,
+        }).collect();
+        assert_eq!(funding.len(), 1);
+        assert!(!funding[0].rates.is_empty());
+        assert!(funding[0].payments.is_empty());
+        assert_eq!(replay.state.positions.lock().await[0].margin, pos.margin);
+
+        let req = order("user", 1, 100_000, Liquidity::Taker);
+        assert_eq!(orders::place(&replay.state, &req).await.unwrap_err(), OrderError::Shadow);
+        assert_eq!(replay.state.positions.lock().await.len(), 1);
+    }
+
+    #[test]
+    fn test_backtest_parses_price_history() {
+        let ticks = backtest::parse_prices(
+            "timestamp,symbol,mark,index\n1767571260000,BTC-USD,49000,48990\n2026-01-05T00:00:00Z,BTC-USD,50000,\n",
+        ).unwrap();
+        assert_eq!(ticks.len(), 2);
+        assert_eq!((ticks[0].mark, ticks[0].index), (50_000, None));
+        assert_eq!((ticks[1].mark, ticks[1].index), (49_000, Some(48_990)));
+        assert!(backtest::parse_prices("2026-01-05T00:00:00Z,BTC-USD").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backtest_replays_prices_without_a_database() {
+        let mut pos = position("user", 100, 100_000, true);
+        pos.margin = 400_000; // 4% at entry, 2% at 98k
+        let snapshot = Snapshot { positions: vec![pos], ..Default::default() };
+        let prices = backtest::parse_prices(
+            "2026-01-05T00:00:00Z,BTC-USD,100000\n2026-01-05T00:01:00Z,BTC-USD,98000\n2026-01-05T00:02:00Z,BTC-USD,98000\n",
+        ).unwrap();
+        let report = backtest::run(EngineConfig::default(), snapshot, &prices, 10_000).await.unwrap();
+        assert_eq!(report.passes, 13);
+        assert!(report.liquidations > 0);
+        let btc = &report.symbols["BTC-USD"];
+        assert_eq!(btc.liquidations, report.liquidations);
+        assert_eq!(btc.liquidated_notional, report.liquidated_notional);
+        assert!(report.insurance.len() > 1);
+        assert!(report.symbols_csv().lines().last().unwrap().starts_with("total,"));
+    }
+
+    #[tokio::test]
+    async fn test_backtest_reports_bad_debt_the_fund_could_not_cover() {
+        // 100_000 under water at 80k, with nobody on the other side to
+        // deleverage or haircut and 30_000 in the fund
+        let cfg = EngineConfig {
+            insurance: InsuranceConfig { shared_balance: 30_000, markets: HashMap::new() },
+            ..Default::default()
+        };
+        let mut pos = position("user", 10, 100_000, true);
+        pos.margin = 100_000;
+        let snapshot = Snapshot { positions: vec![pos], ..Default::default() };
+        let prices = backtest::parse_prices(
+            "2026-01-05T00:00:00Z,BTC-USD,100000\n2026-01-05T00:00:10Z,BTC-USD,80000\n2026-01-05T00:00:20Z,BTC-USD,80000\n",
+        ).unwrap();
+        let report = backtest::run(cfg, snapshot, &prices, 10_000).await.unwrap();
+
+        assert_eq!((report.bad_debt, report.unabsorbed), (30_000, 70_000));
+        assert_eq!(report.symbols["BTC-USD"].unabsorbed, 70_000);
+        assert_eq!(report.insurance.last().unwrap().balance, 0);
+        let total = report.symbols_csv().lines().last().unwrap().to_string();
+        assert!(total.starts_with("total,") && total.contains(",30000,70000,"));
+    }
+
+    #[tokio::test]
+    async fn test_takeover_counts_against_the_shared_throttle() {
+        let cfg = EngineConfig {
+            throttle: ThrottleConfig { enabled: true, max_notional: Some(10_000), ..Default::default() },
+            ..Default::default()
+        };
+        let mut pos = position("user", 100, 100, true);
+        pos.margin = 1_100;
+        let snapshot = Snapshot { positions: vec![pos.clone()], ..Default::default() };
+        let replay = Replay::new(cfg, snapshot, Utc::now()).await.unwrap();
+        replay.state.oracle.set("BTC-USD", 90, None).await;
+
+        let rec = replay.state.takeover("liq", pos.id, 10).await.unwrap();
+        assert_eq!((rec.liquidator.as_str(), rec.liquidated_size), ("liq", 10));
+        let state = &replay.state;
+        let left = state.throttle.lock().await.allowance(&state.config.throttle, "BTC-USD", state.clock.now());
+        assert_eq!(left, 10_000 - 900);
+    }
+
+    #[tokio::test]
+    async fn test_sim_clock_wakes_sleepers_only_when_advanced() {
+        let t0 = Utc::now();
+        let clock = std::sync::Arc::new(SimClock::new(t0));
+        let sleeper = {
+            let clock = clock.clone();
+            tokio::spawn(async move {
+                clock.sleep(std::time::Duration::from_millis(1_200)).await;
+                clock.now()
+            })
+        };
+        tokio::task::yield_now().await;
+
+        clock.advance(std::time::Duration::from_millis(1_000));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        // never runs backwards
+        clock.set(t0);
+        assert_eq!(clock.now(), t0 + Duration::milliseconds(1_000));
+
+        clock.advance(std::time::Duration::from_millis(500));
+        assert_eq!(sleeper.await.unwrap(), t0 + Duration::milliseconds(1_500));
+    }
 }
@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::engine::config::ThrottleConfig;
+
+/// A liquidatable position held back because the window's notional
+/// budget was used up.
+#[derive(Clone, Debug, Serialize)]
+pub struct Backlogged {
+    pub position_id: Uuid,
+    pub position_owner: String,
+    pub symbol: String,
+    pub margin_ratio: f64,
+    /// Notional the step would have closed.
+    pub notional: i64,
+    pub queued_since: DateTime<Utc>,
+}
+
+/// Notional liquidated over the last `window_ms`, and the positions
+/// waiting for budget, most severe first.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Throttle {
+    #[serde(skip)]
+    spent: VecDeque<(DateTime<Utc>, String, i64)>,
+    pub backlog: Vec<Backlogged>,
+}
+
+impl Throttle {
+    /// Forgets notional liquidated before the current window.
+    pub fn expire(&mut self, cfg: &ThrottleConfig, now: DateTime<Utc>) {
+        let since = now - Duration::milliseconds(cfg.window_ms as i64);
+        while self.spent.front().is_some_and(|(t, _, _)| *t <= since) {
+            self.spent.pop_front();
+        }
+    }
+
+    /// Notional liquidated in the window, on `symbol` or across all symbols.
+    pub fn used(&self, symbol: Option<&str>) -> i64 {
+        self.spent
+            .iter()
+            .filter(|(_, s, _)| symbol.is_none_or(|symbol| s == symbol))
+            .map(|(_, _, n)| *n)
+            .sum()
+    }
+
+    /// Notional `symbol` may still liquidate in the current window.
+    pub fn allowance(&mut self, cfg: &ThrottleConfig, symbol: &str, now: DateTime<Utc>) -> i64 {
+        if !cfg.enabled { return i64::MAX; }
+        self.expire(cfg, now);
+        let global = cfg.max_notional.map_or(i64::MAX, |cap| cap - self.used(None));
+        let market = cfg.symbols.get(symbol).map_or(i64::MAX, |cap| cap - self.used(Some(symbol)));
+        global.min(market).max(0)
+    }
+
+    pub fn record(&mut self, symbol: &str, notional: i64, now: DateTime<Utc>) {
+        if notional > 0 {
+            self.spent.push_back((now, symbol.to_string(), notional));
+        }
+    }
+
+    /// Replaces the backlog with this pass's, keeping when each position
+    /// was first queued.
+    pub fn queue(&mut self, mut backlog: Vec<Backlogged>) {
+        for b in &mut backlog {
+            if let Some(prev) = self.backlog.iter().find(|p| p.position_id == b.position_id) {
+                b.queued_since = prev.queued_since;
+            }
+        }
+        self.backlog = backlog;
+    }
+}
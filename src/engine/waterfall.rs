@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::engine::accounts::Accounts;
+use crate::engine::auction::Auction;
+use crate::engine::backstop::BackstopPool;
+use crate::engine::config::{EngineConfig, FeeDestination, StageKind, WaterfallConfig};
+use crate::engine::execution::{ExecutionVenue, Fill};
+use crate::engine::insurance::InsuranceFunds;
+use crate::engine::models::{LiquidationKind, LiquidationRecord, LiquidationStage, Position, SocializedLossRecord};
+use crate::engine::{adl, fees, socialized_loss};
+
+/// Everything a stage may touch. Stages are synchronous; the executor
+/// holds the locks, runs the waterfall and persists the outcome afterwards.
+pub struct StageContext<'a> {
+    pub config: &'a EngineConfig,
+    /// Every position, for ADL and socialized loss. The one being
+    /// liquidated is worked on in `Liquidation::position` instead.
+    pub positions: &'a mut [Position],
+    /// Cross-margin collateral, for cross ADL counterparties.
+    pub accounts: &'a mut Accounts,
+    pub insurance: &'a mut InsuranceFunds,
+    pub backstop: &'a mut BackstopPool,
+    pub venue: &'a dyn ExecutionVenue,
+    pub marks: &'a HashMap<String, i64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// A partial step of `step_size`; unfilled size stays on the position.
+    Step,
+    /// Closing whatever is left of a position with nothing left or negative equity.
+    CloseOut,
+}
+
+/// Size the backstop pool took over, and the fee the fund paid it.
+#[derive(Clone, Debug)]
+pub struct BackstopFill {
+    pub size: i64,
+    pub price: i64,
+    pub fee: i64,
+}
+
+/// What the stages did, for the executor to persist and broadcast.
+#[derive(Clone, Debug, Default)]
+pub struct Outcome {
+    pub records: Vec<LiquidationRecord>,
+    pub haircuts: Vec<SocializedLossRecord>,
+    /// Penalty shares (and liquidation fees, if routed there) already
+    /// credited to the insurance fund.
+    pub contributions: Vec<i64>,
+    /// Liquidation fees owed to the fee account.
+    pub fees: i64,
+    pub backstop: Vec<BackstopFill>,
+    pub auction: Option<Auction>,
+    /// Deficit left once every stage has run.
+    pub unabsorbed: i64,
+}
+
+/// One pass of a position through the waterfall.
+#[derive(Clone, Debug)]
+pub struct Liquidation {
+    /// Working copy of the position; written back by the executor.
+    pub position: Position,
+    pub mark: i64,
+    pub now: DateTime<Utc>,
+    pub mode: Mode,
+    /// Size still to be closed in this pass.
+    pub remaining: i64,
+    /// Close-out only: equity at mark behind this pass, net of what the
+    /// stages have realized or absorbed so far. Negative is a deficit.
+    pub balance: i64,
+    pub insurance_covered: i64,
+    pub outcome: Outcome,
+}
+
+fn equity(pos: &Position, mark: i64) -> i64 {
+    (pos.margin as i128 + pos.unrealized_pnl(mark)) as i64
+}
+
+impl Liquidation {
+    pub fn step(position: Position, size: i64, mark: i64, now: DateTime<Utc>) -> Self {
+        let remaining = size.min(position.size).max(0);
+        Self { position, mark, now, mode: Mode::Step, remaining, balance: 0, insurance_covered: 0, outcome: Outcome::default() }
+    }
+
+    pub fn close_out(position: Position, mark: i64, now: DateTime<Utc>) -> Self {
+        let remaining = position.size.max(0);
+        let balance = equity(&position, mark);
+        Self { position, mark, now, mode: Mode::CloseOut, remaining, balance, insurance_covered: 0, outcome: Outcome::default() }
+    }
+
+    pub fn is_bankrupt(&self) -> bool {
+        self.mode == Mode::CloseOut && self.balance < 0
+    }
+
+    /// Price at which closing `remaining` brings `balance` to exactly zero.
+    pub fn bankruptcy_price(&self) -> i64 {
+        // the remainder as if opened at mark with `balance` as its margin
+        let remainder = Position {
+            size: self.remaining,
+            entry_price: self.mark,
+            margin: self.balance,
+            ..self.position.clone()
+        };
+        adl::bankruptcy_price(&remainder)
+    }
+
+    /// Size closed on the source position in this pass.
+    pub fn closed(&self) -> i64 {
+        self.outcome.records.iter()
+            .filter(|r| r.position_id == self.position.id)
+            .map(|r| r.liquidated_size)
+            .sum()
+    }
+
+    /// Closes up to `size` of what's left at `price` and records it.
+    /// Partial steps pay the liquidation penalty; an auction's discount to
+    /// mark is the taker's reward. The liquidation fee comes out of
+    /// whatever equity is left. Returns the size closed.
+    pub fn close(&mut self, ctx: &mut StageContext, size: i64, price: i64, liquidator: &str, kind: LiquidationKind) -> i64 {
+        let size = size.min(self.remaining);
+        if size <= 0 { return 0; }
+
+        let mark = self.mark;
+        let pos = &mut self.position;
+        let (tier, rates) = ctx.config.fees.tier(&pos.owner);
+        let (tier, notional_fee) = (tier.to_string(), fees::fee(size as i128 * price as i128, rates.liquidation_bps));
+        let realized = pos.pnl(size, price) as i64;
+        let (margin_before, margin_after, reward, contribution, slippage, fee) = match self.mode {
+            Mode::Step => {
+                let cost = Fill { filled: size, vwap: price }.slippage(pos.is_long, mark);
+                let margin_before = equity(pos, mark);
+                // the closed part's PnL, slippage included, settles into margin
+                pos.realize(size, price);
+                let equity = equity(pos, mark);
+
+                // penalty comes out of the remaining equity, split liquidator / fund
+                let (reward, contribution) = match kind {
+                    LiquidationKind::Partial => ctx.config.penalty.split(size as i128 * price as i128, equity),
+                    _ => (0, 0),
+                };
+                let fee = notional_fee.min((equity - reward - contribution).max(0));
+                pos.margin -= reward + contribution + fee;
+                let margin_after = equity - reward - contribution - fee;
+
+                if kind == LiquidationKind::Auction {
+                    (margin_before, margin_after, cost, 0, 0, fee)
+                } else {
+                    (margin_before, margin_after, reward, contribution, cost, fee)
+                }
+            }
+            Mode::CloseOut => {
+                // what closing at `price` instead of mark costs; negative
+                // when a taker pays the bankruptcy price
+                let per_unit = if pos.is_long { mark - price } else { price - mark };
+                let cost = (size as i128 * per_unit as i128) as i64;
+                let share = ((self.balance as i128 * size as i128) / self.remaining as i128) as i64;
+                // a bankrupt slice pays no fee
+                let fee = notional_fee.min((share - cost).max(0));
+                // as in a step, the closed part's PnL settles into margin
+                pos.realize(size, price);
+                pos.margin -= fee;
+                self.balance -= cost + fee;
+                (share, 0, 0, 0, cost.max(0), fee)
+            }
+        };
+        if pos.size <= 0 {
+            pos.open = false;
+        }
+        self.remaining -= size;
+
+        if contribution > 0 {
+            ctx.insurance.credit(&pos.symbol, contribution);
+            self.outcome.contributions.push(contribution);
+        }
+        if fee > 0 {
+            match ctx.config.fees.destination {
+                FeeDestination::FeeAccount => self.outcome.fees += fee,
+                FeeDestination::InsuranceFund => {
+                    ctx.insurance.credit(&pos.symbol, fee);
+                    self.outcome.contributions.push(fee);
+                }
+            }
+        }
+
+        self.outcome.records.push(LiquidationRecord {
+            id: Uuid::new_v4(),
+            position_id: pos.id,
+            position_owner: pos.owner.clone(),
+            liquidator: liquidator.to_string(),
+            symbol: pos.symbol.clone(),
+            liquidated_size: size,
+            liquidation_price: price,
+            margin_before,
+            margin_after,
+            liquidator_reward: reward,
+            insurance_contribution: contribution,
+            slippage,
+            bad_debt: 0,
+            unabsorbed: 0,
+            realized_pnl: realized,
+            unrealized_pnl: pos.unrealized_pnl(mark) as i64,
+            liquidation_fee: fee,
+            fee_tier: tier,
+            kind,
+            timestamp: self.now,
+        });
+        size
+    }
+
+    /// Moves as much of what's left as the backstop pool's leverage limit
+    /// allows into the pool at `price`. Its fee is paid by the insurance
+    /// fund. Returns the size taken.
+    pub fn backstop(&mut self, ctx: &mut StageContext, price: i64, kind: LiquidationKind) -> i64 {
+        let cfg = &ctx.config.backstop;
+        if !cfg.enabled || self.remaining <= 0 { return 0; }
+
+        let taken = self.remaining.min(ctx.backstop.capacity(cfg, ctx.marks, self.position.is_long, price, self.mark));
+        if taken <= 0 { return 0; }
+
+        let fee = ((taken as i128 * price as i128 * cfg.fee_bps as i128) / 10_000) as i64;
+        let fee = ctx.insurance.cover(&self.position.symbol, fee, ctx.marks);
+        ctx.backstop.take_over(&self.position, taken, price, fee);
+        self.outcome.backstop.push(BackstopFill { size: taken, price, fee });
+        self.close(ctx, taken, price, "backstop", kind)
+    }
+
+    /// Moves what's left onto the insurance fund's book at mark. In a
+    /// close-out the fund also covers the deficit the position is left
+    /// with, as far as its balance goes. Returns the size taken.
+    pub fn insurance_takeover(&mut self, ctx: &mut StageContext, kind: LiquidationKind) -> i64 {
+        let taken = self.remaining;
+        if taken > 0 {
+            ctx.insurance.take_over(&self.position, taken, self.mark);
+            self.close(ctx, taken, self.mark, "insurance_fund", kind);
+        }
+        if self.is_bankrupt() {
+            let covered = ctx.insurance.cover(&self.position.symbol, -self.balance, ctx.marks);
+            self.balance += covered;
+            self.insurance_covered += covered;
+        }
+        taken
+    }
+
+    /// After the last stage: a close-out closes any size nobody took at mark
+    /// and books what the insurance fund paid as the position's bad debt.
+    pub fn finish(&mut self, ctx: &mut StageContext) {
+        if self.mode != Mode::CloseOut { return; }
+
+        if self.remaining > 0 {
+            self.close(ctx, self.remaining, self.mark, "executor", LiquidationKind::Full);
+        }
+        if self.closed() == 0 {
+            // nothing left to close, but the closure itself is still recorded
+            let pos = &self.position;
+            self.outcome.records.push(LiquidationRecord {
+                id: Uuid::new_v4(),
+                position_id: pos.id,
+                position_owner: pos.owner.clone(),
+                liquidator: "executor".into(),
+                symbol: pos.symbol.clone(),
+                liquidated_size: 0,
+                liquidation_price: self.mark,
+                margin_before: equity(pos, self.mark),
+                margin_after: 0,
+                liquidator_reward: 0,
+                insurance_contribution: 0,
+                slippage: 0,
+                bad_debt: 0,
+                unabsorbed: 0,
+                realized_pnl: 0,
+                unrealized_pnl: pos.unrealized_pnl(self.mark) as i64,
+                liquidation_fee: 0,
+                fee_tier: ctx.config.fees.tier(&pos.owner).0.to_string(),
+                kind: LiquidationKind::Full,
+                timestamp: self.now,
+            });
+        }
+
+        let id = self.position.id;
+        self.outcome.unabsorbed = (-self.balance).max(0);
+        if let Some(rec) = self.outcome.records.iter_mut().rev().find(|r| r.position_id == id) {
+            rec.bad_debt = self.insurance_covered;
+            rec.unabsorbed = self.outcome.unabsorbed;
+        }
+
+        let pos = &mut self.position;
+        // with nothing left open, margin is the balance: what the fund and
+        // socialized loss absorbed no longer counts against it
+        pos.margin = self.balance;
+        pos.open = false;
+        pos.liquidation.stage = LiquidationStage::FullyLiquidated;
+        pos.liquidation.cooldown_until = None;
+    }
+}
+
+/// One step of the liquidation waterfall. A stage that doesn't apply to a
+/// liquidation (wrong mode, disabled, nothing left for it) leaves it alone.
+pub trait Stage: Send + Sync {
+    fn kind(&self) -> StageKind;
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation);
+}
+
+/// Offers a partial step in a Dutch auction instead of closing it; the
+/// size stays on the position until the auction settles. A position with
+/// negative equity can't wait that long and is left to the later stages.
+pub struct AuctionStage;
+
+impl Stage for AuctionStage {
+    fn kind(&self) -> StageKind { StageKind::Auction }
+
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        if liq.mode != Mode::Step || !ctx.config.auction.enabled || liq.remaining <= 0 { return; }
+        if equity(&liq.position, liq.mark) < 0 { return; }
+        let pos = &liq.position;
+        liq.outcome.auction = Some(Auction::new(
+            &ctx.config.auction, pos.id, &pos.owner, &pos.symbol, pos.is_long, liq.remaining, liq.now,
+        ));
+        liq.remaining = 0;
+    }
+}
+
+/// Sends what's left to the execution venue.
+pub struct MarketCloseStage;
+
+impl Stage for MarketCloseStage {
+    fn kind(&self) -> StageKind { StageKind::MarketClose }
+
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        if liq.remaining <= 0 { return; }
+        let pos = &liq.position;
+        let fill = ctx.venue.execute(&pos.symbol, pos.is_long, liq.remaining, liq.mark);
+        let kind = match liq.mode {
+            Mode::Step => LiquidationKind::Partial,
+            Mode::CloseOut => LiquidationKind::Full,
+        };
+        liq.close(ctx, fill.filled, fill.vwap, "executor", kind);
+    }
+}
+
+/// Hands a bankrupt remainder to the backstop pool at its bankruptcy price.
+pub struct BackstopStage;
+
+impl Stage for BackstopStage {
+    fn kind(&self) -> StageKind { StageKind::Backstop }
+
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        if !liq.is_bankrupt() { return; }
+        let price = liq.bankruptcy_price();
+        liq.backstop(ctx, price, LiquidationKind::Full);
+    }
+}
+
+/// If the insurance fund can't cover the deficit, closes what's left
+/// against the top of the ADL queue at the bankruptcy price.
+pub struct AdlStage;
+
+impl Stage for AdlStage {
+    fn kind(&self) -> StageKind { StageKind::Adl }
+
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        if !ctx.config.adl.enabled || !liq.is_bankrupt() || liq.remaining <= 0 { return; }
+        if -liq.balance <= ctx.insurance.available(&liq.position.symbol, ctx.marks) { return; }
+
+        let price = liq.bankruptcy_price();
+        let fills = adl::deleverage(ctx.positions, ctx.accounts, &liq.position, liq.remaining, price, ctx.marks);
+        let filled: i64 = fills.iter().map(|f| f.size).sum();
+
+        for fill in &fills {
+            let Some(cp) = ctx.positions.iter().find(|p| p.id == fill.position_id) else { continue };
+            liq.outcome.records.push(LiquidationRecord {
+                id: Uuid::new_v4(),
+                position_id: cp.id,
+                position_owner: cp.owner.clone(),
+                liquidator: "executor".into(),
+                symbol: cp.symbol.clone(),
+                liquidated_size: fill.size,
+                liquidation_price: price,
+                margin_before: fill.margin_before,
+                margin_after: fill.margin_after,
+                liquidator_reward: 0,
+                insurance_contribution: 0,
+                slippage: 0,
+                bad_debt: 0,
+                unabsorbed: 0,
+                realized_pnl: fill.realized,
+                unrealized_pnl: cp.unrealized_pnl(liq.mark) as i64,
+                liquidation_fee: 0,
+                fee_tier: ctx.config.fees.tier(&cp.owner).0.to_string(),
+                kind: LiquidationKind::Adl,
+                timestamp: liq.now,
+            });
+        }
+        liq.close(ctx, filled, price, "executor", LiquidationKind::Full);
+    }
+}
+
+/// Covers the deficit from the market's insurance fund, as far as it goes.
+pub struct InsuranceFundStage;
+
+impl Stage for InsuranceFundStage {
+    fn kind(&self) -> StageKind { StageKind::InsuranceFund }
+
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        if !liq.is_bankrupt() { return; }
+        let covered = ctx.insurance.cover(&liq.position.symbol, -liq.balance, ctx.marks);
+        liq.balance += covered;
+        liq.insurance_covered += covered;
+    }
+}
+
+/// Haircuts the winners in the same market for whatever deficit is left.
+pub struct SocializedLossStage;
+
+impl Stage for SocializedLossStage {
+    fn kind(&self) -> StageKind { StageKind::SocializedLoss }
+
+    fn apply(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        let cfg = &ctx.config.socialized_loss;
+        if !cfg.enabled || !liq.is_bankrupt() { return; }
+
+        let deficit = -liq.balance;
+        let (haircuts, unabsorbed) =
+            socialized_loss::socialize(ctx.positions, &liq.position.symbol, liq.position.id, deficit, liq.mark, cfg.mode, liq.now);
+        liq.balance += deficit - unabsorbed;
+        liq.outcome.haircuts.extend(haircuts);
+    }
+}
+
+pub fn stage(kind: StageKind) -> Box<dyn Stage> {
+    match kind {
+        StageKind::Auction => Box::new(AuctionStage),
+        StageKind::MarketClose => Box::new(MarketCloseStage),
+        StageKind::Backstop => Box::new(BackstopStage),
+        StageKind::Adl => Box::new(AdlStage),
+        StageKind::InsuranceFund => Box::new(InsuranceFundStage),
+        StageKind::SocializedLoss => Box::new(SocializedLossStage),
+    }
+}
+
+/// An ordered list of stages a liquidation goes through until its size is
+/// closed and its deficit absorbed.
+pub struct Waterfall {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Waterfall {
+    pub fn new(stages: Vec<Box<dyn Stage>>) -> Self {
+        Self { stages }
+    }
+
+    pub fn from_config(cfg: &WaterfallConfig) -> Self {
+        Self::new(cfg.stages.iter().map(|k| stage(*k)).collect())
+    }
+
+    pub fn kinds(&self) -> Vec<StageKind> {
+        self.stages.iter().map(|s| s.kind()).collect()
+    }
+
+    pub fn run(&self, ctx: &mut StageContext, liq: &mut Liquidation) {
+        for stage in &self.stages {
+            if liq.remaining <= 0 && !liq.is_bankrupt() { break; }
+            stage.apply(ctx, liq);
+        }
+        liq.finish(ctx);
+    }
+}
@@ -1,13 +1,12 @@
 use std::net::SocketAddr;
-use axum::{routing::get, Router};
+use axum::{routing::{get, post}, Router};
 use dotenvy::dotenv;
 use std::sync::Arc;
 use sqlx::PgPool;
 use tokio::sync::broadcast;
 use log::info;
 
-mod engine;
-mod api;
+use goquant_liquidation_backend::{api, engine};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -23,12 +22,20 @@ async fn main() -> anyhow::Result<()> {
     sqlx::migrate!("./migrations").run(&db).await?;
 
     // Broadcast channel (for WS events)
-    let (tx, _rx) = broadcast::channel::<engine::models::LiquidationEvent>(256);
+    let (tx, _rx) = broadcast::channel::<engine::models::EngineEvent>(256);
     let tx = Arc::new(tx);
 
+    // Engine tunables
+    let config = engine::config::EngineConfig::load()?;
+
     // Create engine state
     let state: Arc<engine::EngineState> =
-        Arc::new(engine::EngineState::new(db.clone(), tx.clone()).await?);
+        Arc::new(engine::EngineState::new(
+            engine::store::Store::Postgres(db.clone()),
+            tx.clone(),
+            config,
+            Arc::new(engine::clock::SystemClock),
+        ).await?);
 
     // Start engine background tasks
     {
@@ -44,7 +51,7 @@ async fn main() -> anyhow::Result<()> {
 
         let s = state.clone();
         tokio::spawn(async move {
-            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+            s.clock.sleep(std::time::Duration::from_secs(2)).await;
 
             let mut positions = s.positions.lock().await;
 
@@ -58,8 +65,35 @@ async fn main() -> anyhow::Result<()> {
                 is_long: true,
                 leverage: 100,
                 open: true,
+                realized_pnl: 0,
+                haircuts: 0,
+                margin_mode: Default::default(),
+                liquidation: Default::default(),
             });
 
+            // a hedged cross-margin account: the short offsets the long
+            for (symbol, size, entry_price, is_long) in [
+                ("BTC-USD", 10, 50_000_000_000, true),
+                ("ETH-USD", 180, 2_800_000_000, false),
+            ] {
+                positions.push(engine::models::Position {
+                    id: Uuid::new_v4(),
+                    owner: "demo-cross".to_string(),
+                    symbol: symbol.to_string(),
+                    size,
+                    entry_price,
+                    margin: 0,
+                    is_long,
+                    leverage: 20,
+                    open: true,
+                    realized_pnl: 0,
+                    haircuts: 0,
+                    margin_mode: engine::models::MarginMode::Cross,
+                    liquidation: Default::default(),
+                });
+            }
+            let _ = s.accounts.lock().await.deposit("demo-cross", 30_000_000_000);
+
             println!("Added mock BTC position for demo.");
         });
     }
@@ -69,7 +103,30 @@ async fn main() -> anyhow::Result<()> {
         .route("/health", get(api::http::health))
         .route("/insurance", get(api::http::get_insurance))
         .route("/liquidations", get(api::http::get_liquidations))
+        .route("/liquidations/queue", get(api::http::get_liquidation_queue))
+        .route("/shadow", get(api::http::get_shadow))
+        .route("/positions", post(api::http::post_position))
         .route("/positions/pending", get(api::http::get_pending))
+        .route("/adl/:owner", get(api::http::get_adl_rank))
+        .route("/auctions", get(api::http::get_auctions))
+        .route("/auctions/:id/bid", post(api::http::post_auction_bid))
+        .route("/liquidators", post(api::http::register_liquidator))
+        .route("/liquidators/me", get(api::http::get_liquidator_account))
+        .route("/liquidatable", get(api::http::get_liquidatable))
+        .route("/takeover", post(api::http::post_takeover))
+        .route("/backstop", get(api::http::get_backstop))
+        .route("/backstop/deposit", post(api::http::backstop_deposit))
+        .route("/backstop/withdraw", post(api::http::backstop_withdraw))
+        .route("/accounts/:owner", get(api::http::get_account))
+        .route("/accounts/:owner/key", post(api::http::issue_account_key))
+        .route("/accounts/:owner/deposit", post(api::http::account_deposit))
+        .route("/accounts/:owner/withdraw", post(api::http::account_withdraw))
+        .route("/funding", get(api::http::get_funding))
+        .route("/fees", get(api::http::get_fees))
+        .route("/admin/controls", post(api::http::post_controls))
+        .route("/admin/controls/:symbol", post(api::http::post_symbol_controls))
+        .route("/admin/insurance/unwind", post(api::http::post_insurance_unwind))
+        .route("/admin/backstop/unwind", post(api::http::post_backstop_unwind))
         .route("/ws", get(api::websocket::ws_handler))
         .with_state(state.clone());
 
@@ -0,0 +1,149 @@
+use goquant_liquidation_backend::engine::config::{MarketConfig, VenueKind};
+use goquant_liquidation_backend::engine::models::LiquidationKind::{Adl, Auction, Full, Partial};
+use goquant_liquidation_backend::engine::scenario::{ExpectLiquidation as Expect, Scenario};
+
+/// One dollar, scaled.
+const USD: i64 = 1_000_000;
+
+#[tokio::test]
+async fn test_liquidation_trigger_flow() {
+    // 2% margin at 50x; a $500 drop leaves 0.33%, under maintenance, so the
+    // executor halves the position, then keeps stepping once per cooldown
+    Scenario::new()
+        .long("alice", "BTC-USD", 100, 30_000 * USD, 60_000 * USD, 50)
+        .long("bob", "BTC-USD", 10, 30_000 * USD, 100_000 * USD, 5)
+        .short("carol", "BTC-USD", 50, 30_000 * USD, 300_000 * USD, 10)
+        .price(0, "BTC-USD", 30_000 * USD)
+        .price(2_000, "BTC-USD", 29_500 * USD)
+        .expect_liquidation(Expect::of("alice").kind(Partial).size(50).price(29_500 * USD).by(2_000))
+        .expect_liquidation(Expect::of("alice").kind(Partial).size(25).by(7_000))
+        .expect_liquidation(Expect::of("alice").kind(Partial).size(12).by(12_000))
+        .expect_untouched("bob")
+        .expect_untouched("carol")
+        .expect_insurance(1_000, USD)
+        .expect_insurance(2_000, USD + 5_000 * USD)
+        .run_for(15_000)
+        .check()
+        .await;
+}
+
+#[tokio::test]
+async fn test_bankrupt_position_deleverages_counterparty_when_fund_is_short() {
+    // a $1,000 gap puts dave $7,000 under water with an empty book to sell
+    // into; the $1 fund can't cover it, so erin's short is closed against
+    // him at his bankruptcy price
+    let outcome = Scenario::new()
+        .config(|c| {
+            c.execution.venue = VenueKind::OrderBook;
+            c.market_defaults.book_level_size = 0;
+        })
+        .long("dave", "BTC-USD", 10, 30_000 * USD, 3_000 * USD, 100)
+        .short("erin", "BTC-USD", 10, 30_000 * USD, 30_000 * USD, 10)
+        .price(0, "BTC-USD", 30_000 * USD)
+        .price(1_000, "BTC-USD", 29_000 * USD)
+        .expect_liquidation(Expect::of("dave").kind(Full).size(10).price(29_700 * USD).bad_debt(0).by(1_000))
+        .expect_liquidation(Expect::of("erin").kind(Adl).size(10).price(29_700 * USD).by(1_000))
+        .expect_insurance(5_000, USD)
+        .run_for(5_000)
+        .check()
+        .await;
+
+    assert!(!outcome.position_of("dave", "BTC-USD").unwrap().open);
+    assert!(!outcome.position_of("erin", "BTC-USD").unwrap().open);
+}
+
+#[tokio::test]
+async fn test_cross_account_steps_down_through_cooldowns() {
+    // $4,000 of equity on $288,000 of ETH: 1.4% against 2.5% maintenance,
+    // cut a quarter at a time, every 2s
+    Scenario::new()
+        .market("ETH-USD", MarketConfig { cooldown_ms: 2_000, max_step_bps: 2_500, ..Default::default() })
+        .cross("frank", "ETH-USD", true, 100, 3_000 * USD, 20)
+        .deposit("frank", 16_000 * USD)
+        .price(0, "ETH-USD", 3_000 * USD)
+        .price(1_000, "ETH-USD", 2_880 * USD)
+        .expect_liquidation(Expect::of("frank").kind(Partial).size(25).by(1_000))
+        .expect_liquidation(Expect::of("frank").kind(Partial).size(18).by(3_000))
+        .expect_liquidation(Expect::of("frank").kind(Partial).size(14).by(5_000))
+        .expect_insurance(1_000, USD + 900 * USD)
+        .expect_insurance(3_000, USD + 1_548 * USD)
+        .expect_insurance(10_000, USD + 1_845_200_000)
+        .run_for(10_000)
+        .check()
+        .await;
+}
+
+#[tokio::test]
+async fn test_expired_auction_moves_to_insurance_fund_book() {
+    // nobody bids on alice's first step, so after 5s the fund takes it over at mark
+    let outcome = Scenario::new()
+        .config(|c| {
+            c.auction.enabled = true;
+            c.auction.timeout_ms = 5_000;
+        })
+        .long("alice", "BTC-USD", 100, 30_000 * USD, 60_000 * USD, 50)
+        .price(0, "BTC-USD", 29_500 * USD)
+        .expect_liquidation(Expect::of("alice").kind(Auction).size(50).price(29_500 * USD).by(5_000))
+        .expect_insurance(5_000, USD)
+        .check()
+        .await;
+
+    let (ms, rec) = outcome.records_of("alice")[0];
+    assert_eq!((*ms, rec.liquidator.as_str()), (5_000, "insurance_fund"));
+    let book: Vec<_> = outcome.insurance_positions.iter().map(|p| (p.owner.as_str(), p.size, p.entry_price, p.is_long)).collect();
+    assert_eq!(book, vec![("insurance_fund", 50, 29_500 * USD, true)]);
+}
+
+#[tokio::test]
+async fn test_bankrupt_position_skips_its_auction() {
+    // alice's first step is on auction for 30s when a gap leaves her $40,000
+    // under water: she's closed out on the next pass and the fund pays
+    Scenario::new()
+        .config(|c| {
+            c.auction.enabled = true;
+            c.insurance.shared_balance = 100_000 * USD;
+        })
+        .long("alice", "BTC-USD", 100, 30_000 * USD, 60_000 * USD, 50)
+        .price(0, "BTC-USD", 29_500 * USD)
+        .price(2_000, "BTC-USD", 29_000 * USD)
+        .expect_liquidation(Expect::of("alice").kind(Partial).size(50).price(29_000 * USD).by(2_000))
+        .expect_liquidation(Expect::of("alice").kind(Full).size(50).price(29_000 * USD).bad_debt(40_000 * USD).by(2_000))
+        .expect_insurance(2_000, 60_000 * USD)
+        .run_for(3_000)
+        .check()
+        .await;
+}
+
+#[tokio::test]
+async fn test_bankrupt_position_bypasses_exhausted_throttle() {
+    // no notional budget at all: bob, still $10,000 above water, waits in
+    // the backlog, but dave is $7,000 under and is liquidated anyway
+    Scenario::new()
+        .config(|c| {
+            c.throttle.enabled = true;
+            c.throttle.max_notional = Some(0);
+        })
+        .long("dave", "BTC-USD", 10, 30_000 * USD, 3_000 * USD, 100)
+        .long("bob", "BTC-USD", 100, 30_000 * USD, 110_000 * USD, 50)
+        .price(0, "BTC-USD", 30_000 * USD)
+        .price(1_000, "BTC-USD", 29_000 * USD)
+        .expect_liquidation(Expect::of("dave").kind(Partial).size(5).price(29_000 * USD).by(1_000))
+        .expect_liquidation(Expect::of("dave").kind(Full).size(5).price(29_000 * USD).by(1_000))
+        .expect_untouched("bob")
+        .run_for(5_000)
+        .check()
+        .await;
+}
+
+#[tokio::test]
+#[should_panic(expected = "alice liquidation #2: never booked")]
+async fn test_scenario_reports_unmet_expectations() {
+    Scenario::new()
+        .long("alice", "BTC-USD", 100, 30_000 * USD, 60_000 * USD, 50)
+        .price(0, "BTC-USD", 29_500 * USD)
+        .expect_liquidation(Expect::of("alice").kind(Partial).size(50))
+        .expect_liquidation(Expect::of("alice").kind(Partial).size(25))
+        .run_for(3_000)
+        .check()
+        .await;
+}